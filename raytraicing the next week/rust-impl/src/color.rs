@@ -5,21 +5,24 @@ pub type Color = Vec3;
 pub const WHITE:Color = Color::new(1.0, 1.0, 1.0);
 pub const BLACK:Color = Color::ZERO;
 
+/// The sRGB transfer function (IEC 61966-2-1), linear segment near black included.
 #[inline]
-pub fn linear_to_gamma(linear_comp: f64) -> f64 {
-    if linear_comp > 0. {
-        f64::sqrt(linear_comp)
-    } else {
+pub fn linear_to_srgb(linear_comp: f64) -> f64 {
+    if linear_comp <= 0. {
         0.
+    } else if linear_comp <= 0.0031308 {
+        12.92 * linear_comp
+    } else {
+        1.055 * linear_comp.powf(1. / 2.4) - 0.055
     }
 }
 
 const COLOR_INTERVALL: Intervall = Intervall::new(0., 0.999);
 pub fn write_color_to_pixel_buff(pixel_buff: &mut [[u8; 3]], pos: usize, color: Color) {
 
-    let r = linear_to_gamma(color.x());
-    let g = linear_to_gamma(color.y());
-    let b = linear_to_gamma(color.z());
+    let r = linear_to_srgb(color.x());
+    let g = linear_to_srgb(color.y());
+    let b = linear_to_srgb(color.z());
 
     // Clamp and convert to byte
     pixel_buff[pos] = [
@@ -1,23 +1,41 @@
-use std::rc::Rc;
+use std::{collections::HashMap, f64::consts::PI, fmt::Display, rc::Rc};
 
-use crate::{light::{material::Material, Hittable}, math::{dot, Point3}};
+use crate::{light::{material::Material, ray::Ray, texture::Texture, HitRecord, Hittable}, math::{cross, dot, hash_to_unit, normalize, Intervall, Point3, Vec3}, render::stats::{self, Counter}};
 
 
 
 pub struct Sphere{
     center : Point3,
     radius : f64,
-    mat : Rc<dyn Material>
+    mat : Rc<dyn Material>,
+    light_id : Option<usize>,
 }
 
 impl Sphere {
     pub fn new(center:Point3, radius: f64, mat: Rc<dyn Material>)->Self{
-        Self { center, radius, mat }
+        Self { center, radius, mat, light_id: None }
+    }
+
+    /// Marks the sphere as the geometry of the scene light `light_id`.
+    pub fn with_light_id(mut self, light_id: usize) -> Self {
+        self.light_id = Some(light_id);
+        self
+    }
+
+    fn get_sphere_uv(p: Point3) -> (f64, f64) {
+        // p: a given point on the sphere of radius one, centered at the origin.
+        // u: returned value [0,1] of angle around the Y axis from X=-1.
+        // v: returned value [0,1] of angle from Y=-1 to Y=+1.
+        let theta = f64::acos(-p.y());
+        let phi = f64::atan2(-p.z(), p.x()) + PI;
+
+        (phi / (2. * PI), theta / PI)
     }
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, ray: &crate::light::ray::Ray, ray_t: crate::math::Intervall, rec: &mut crate::light::HitRecord) -> bool {
+    fn hit(&self, ray: &Ray, ray_t: Intervall, rec: &mut HitRecord) -> bool {
+        stats::count(Counter::PrimitiveTests);
         let oc = self.center - ray.origine;
         let a = ray.direction.length_sq();
         let h = dot(ray.direction, oc);
@@ -41,8 +59,299 @@ impl Hittable for Sphere {
         rec.p = ray.at(root);
         let outward_normal = (rec.p-self.center) / self.radius;
         rec.set_face_normal(*ray, outward_normal);
+        (rec.u, rec.v) = Self::get_sphere_uv(outward_normal);
+        rec.set_tangent(Vec3::new(outward_normal.z(), 0., -outward_normal.x()));
         rec.mat = self.mat.clone();
+        rec.light_id = self.light_id;
 
         true
     }
-}
\ No newline at end of file
+}
+
+
+#[derive(Clone, Copy)]
+pub enum AlphaMode {
+    /// Hits where the opacity is below the threshold are discarded.
+    Cutoff(f64),
+    /// Hits are kept with a probability equal to the opacity, the decision being hashed from
+    /// the hit point so it stays reproducible.
+    Stochastic,
+}
+
+/// Per-texel opacity for cut-out surfaces (leaves, fences, decals...).
+/// Masked hits are rejected inside `Hittable::hit`, so rays, shadow rays included,
+/// continue through the transparent regions.
+#[derive(Clone)]
+pub struct AlphaMask {
+    opacity: Rc<dyn Texture>,
+    mode: AlphaMode,
+}
+
+impl AlphaMask {
+    pub fn new(opacity: Rc<dyn Texture>, mode: AlphaMode) -> Self {
+        Self { opacity, mode }
+    }
+
+    pub fn is_opaque(&self, u: f64, v: f64, p: Point3) -> bool {
+        let alpha = self.opacity.value(u, v, p);
+        let alpha = (alpha.x() + alpha.y() + alpha.z()) / 3.;
+        match self.mode {
+            AlphaMode::Cutoff(threshold) => alpha >= threshold,
+            AlphaMode::Stochastic => alpha > hash_to_unit(&[u, v, p.x(), p.y(), p.z()]),
+        }
+    }
+}
+
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+    mat: Rc<dyn Material>,
+    normal: Vec3,
+    d: f64,
+    alpha: Option<AlphaMask>,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, mat: Rc<dyn Material>) -> Self {
+        let n = cross(u, v);
+        let normal = normalize(n);
+        let d = dot(normal, q);
+        let w = n / dot(n, n);
+
+        Self { q, u, v, w, mat, normal, d, alpha: None }
+    }
+
+    pub fn with_alpha_mask(mut self, alpha: AlphaMask) -> Self {
+        self.alpha = Some(alpha);
+        self
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, ray_t: Intervall, rec: &mut HitRecord) -> bool {
+        stats::count(Counter::PrimitiveTests);
+        let denom = dot(self.normal, ray.direction);
+
+        // No hit if the ray is parallel to the plane.
+        if denom.abs() < 1e-8 {
+            return false;
+        }
+
+        // Return false if the hit point parameter t is outside the ray interval.
+        let t = (self.d - dot(self.normal, ray.origine)) / denom;
+        if !ray_t.contains(t) {
+            return false;
+        }
+
+        // Determine if the hit point lies within the planar shape using its plane coordinates.
+        let intersection = ray.at(t);
+        let planar_hitpt_vector = intersection - self.q;
+        let alpha = dot(self.w, cross(planar_hitpt_vector, self.v));
+        let beta = dot(self.w, cross(self.u, planar_hitpt_vector));
+
+        let unit_intervall = Intervall::new(0., 1.);
+        if !unit_intervall.contains(alpha) || !unit_intervall.contains(beta) {
+            return false;
+        }
+        if self.alpha.as_ref().is_some_and(|mask| !mask.is_opaque(alpha, beta, intersection)) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.p = intersection;
+        (rec.u, rec.v) = (alpha, beta);
+        rec.mat = self.mat.clone();
+        rec.light_id = None;
+        rec.set_face_normal(*ray, self.normal);
+        rec.set_tangent(self.u);
+
+        true
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MeshError {
+    UvCountMismatch { vertices: usize, uvs: usize },
+    IndexOutOfRange(usize),
+}
+
+impl Display for MeshError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MeshError::UvCountMismatch { vertices, uvs } => write!(f, "a mesh needs one uv per vertex, got {uvs} for {vertices} vertices"),
+            MeshError::IndexOutOfRange(i) => write!(f, "vertex index {i} out of range"),
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
+pub struct TriangleMesh {
+    vertices: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    indices: Vec<[usize; 3]>,
+    mat: Rc<dyn Material>,
+    alpha: Option<AlphaMask>,
+}
+
+impl TriangleMesh {
+    /// Builds a mesh with smooth normals computed from the faces. Fails on inconsistent
+    /// input, as meshes usually come from files.
+    pub fn new(vertices: Vec<Point3>, uvs: Vec<(f64, f64)>, indices: Vec<[usize; 3]>, mat: Rc<dyn Material>) -> Result<Self, MeshError> {
+        if vertices.len() != uvs.len() {
+            return Err(MeshError::UvCountMismatch { vertices: vertices.len(), uvs: uvs.len() });
+        }
+        if let Some(&i) = indices.iter().flatten().find(|&&i| i >= vertices.len()) {
+            return Err(MeshError::IndexOutOfRange(i));
+        }
+        Ok(Self::with_normals(vertices, uvs, indices, mat))
+    }
+
+    fn with_normals(vertices: Vec<Point3>, uvs: Vec<(f64, f64)>, indices: Vec<[usize; 3]>, mat: Rc<dyn Material>) -> Self {
+        let mut mesh = Self { vertices, normals: vec![], uvs, indices, mat, alpha: None };
+        mesh.compute_normals();
+        mesh
+    }
+
+    pub fn with_alpha_mask(mut self, alpha: AlphaMask) -> Self {
+        self.alpha = Some(alpha);
+        self
+    }
+
+    fn compute_normals(&mut self) {
+        // Area weighted, as the cross product length is twice the face area.
+        let mut normals = vec![Vec3::ZERO; self.vertices.len()];
+        for &[i0, i1, i2] in self.indices.iter() {
+            let n = cross(self.vertices[i1] - self.vertices[i0], self.vertices[i2] - self.vertices[i0]);
+            for i in [i0, i1, i2] {
+                normals[i] += n;
+            }
+        }
+        self.normals = normals.into_iter()
+            .map(|n| if n.near_zero() { n } else { normalize(n) })
+            .collect();
+    }
+
+    /// Returns a copy of the mesh where each triangle is split `subdivisions` times, and every
+    /// vertex is then pushed along its normal by `scale * height(u, v)`.
+    /// Meant to be applied once, when the mesh is loaded.
+    pub fn displaced(&self, height: &dyn Texture, scale: f64, subdivisions: usize) -> Self {
+        let mut vertices = self.vertices.clone();
+        let mut normals = self.normals.clone();
+        let mut uvs = self.uvs.clone();
+        let mut indices = self.indices.clone();
+
+        for _ in 0..subdivisions {
+            // Midpoints are shared between the two faces of an edge to keep the mesh watertight.
+            let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+            let mut midpoint = |a: usize, b: usize| -> usize {
+                *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    vertices.push(0.5 * (vertices[a] + vertices[b]));
+                    let n = normals[a] + normals[b];
+                    normals.push(if n.near_zero() { n } else { normalize(n) });
+                    uvs.push((0.5 * (uvs[a].0 + uvs[b].0), 0.5 * (uvs[a].1 + uvs[b].1)));
+                    vertices.len() - 1
+                })
+            };
+
+            indices = indices.into_iter().flat_map(|[i0, i1, i2]| {
+                let (m01, m12, m20) = (midpoint(i0, i1), midpoint(i1, i2), midpoint(i2, i0));
+                [[i0, m01, m20], [m01, i1, m12], [m20, m12, i2], [m01, m12, m20]]
+            }).collect();
+        }
+
+        for ((p, n), &(u, v)) in vertices.iter_mut().zip(normals.iter()).zip(uvs.iter()) {
+            let h = height.value(u, v, *p);
+            *p += (scale * (h.x() + h.y() + h.z()) / 3.) * *n;
+        }
+
+        // Subdividing a valid mesh keeps it valid.
+        let mut mesh = Self::with_normals(vertices, uvs, indices, self.mat.clone());
+        mesh.alpha = self.alpha.clone();
+        mesh
+    }
+
+    fn hit_triangle(&self, face: usize, ray: &Ray, ray_t: Intervall, rec: &mut HitRecord) -> bool {
+        // Möller–Trumbore intersection.
+        stats::count(Counter::PrimitiveTests);
+        let [i0, i1, i2] = self.indices[face];
+        let (p0, p1, p2) = (self.vertices[i0], self.vertices[i1], self.vertices[i2]);
+        let e1 = p1 - p0;
+        let e2 = p2 - p0;
+
+        let pvec = cross(ray.direction, e2);
+        let det = dot(e1, pvec);
+        if det.abs() < 1e-12 {
+            return false;
+        }
+        let inv_det = det.recip();
+
+        let tvec = ray.origine - p0;
+        let b1 = dot(tvec, pvec) * inv_det;
+        if !(0. ..=1.).contains(&b1) {
+            return false;
+        }
+        let qvec = cross(tvec, e1);
+        let b2 = dot(ray.direction, qvec) * inv_det;
+        if b2 < 0. || b1 + b2 > 1. {
+            return false;
+        }
+        let t = dot(e2, qvec) * inv_det;
+        if !ray_t.surronds(t) {
+            return false;
+        }
+        let b0 = 1. - b1 - b2;
+
+        let (uv0, uv1, uv2) = (self.uvs[i0], self.uvs[i1], self.uvs[i2]);
+        let u = b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0;
+        let v = b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1;
+        let p = ray.at(t);
+        if self.alpha.as_ref().is_some_and(|alpha| !alpha.is_opaque(u, v, p)) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.p = p;
+        (rec.u, rec.v) = (u, v);
+
+        let geometric_normal = normalize(cross(e1, e2));
+        let shading_normal = b0 * self.normals[i0] + b1 * self.normals[i1] + b2 * self.normals[i2];
+        let out_normal = if shading_normal.near_zero() {
+            geometric_normal
+        } else {
+            normalize(shading_normal)
+        };
+        rec.set_face_normal(*ray, geometric_normal);
+        rec.normal = if rec.front_face { out_normal } else { -out_normal };
+
+        // dp/du from the uv parametrization of the triangle.
+        let (du1, dv1) = (uv1.0 - uv0.0, uv1.1 - uv0.1);
+        let (du2, dv2) = (uv2.0 - uv0.0, uv2.1 - uv0.1);
+        let uv_det = du1 * dv2 - dv1 * du2;
+        let dpdu = if uv_det.abs() < 1e-12 { e1 } else { (dv2 * e1 - dv1 * e2) / uv_det };
+        rec.set_tangent(dpdu);
+
+        rec.mat = self.mat.clone();
+        rec.light_id = None;
+        true
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, ray_t: Intervall, rec: &mut HitRecord) -> bool {
+        stats::count(Counter::AggregateVisits);
+        let mut hit_anything = false;
+        let mut closest_so_far = ray_t.max;
+
+        for face in 0..self.indices.len() {
+            if self.hit_triangle(face, ray, Intervall::new(ray_t.min, closest_so_far), rec) {
+                hit_anything = true;
+                closest_so_far = rec.t;
+            }
+        }
+        hit_anything
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    f64::consts::PI,
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+};
+
+use crate::{
+    color::Color,
+    light::{lights::LightSample, sky::Environment},
+    math::{normalize, Distribution2D, Sampler, Vec3},
+};
+
+/// Equirectangular HDR environment, importance sampled following the luminance of its texels.
+/// The center of the image looks toward -Z, the top row toward +Y.
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    intensity: f64,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height, "an environment map needs width * height pixels");
+
+        // Rows near the poles cover a smaller solid angle, hence the sin(theta) weight.
+        let func: Vec<f64> = pixels
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let sin_theta = f64::sin(PI * ((i / width) as f64 + 0.5) / height as f64);
+                (0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()) * sin_theta
+            })
+            .collect();
+        let distribution = Distribution2D::new(&func, width, height);
+
+        Self { width, height, pixels, intensity: 1., distribution }
+    }
+
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    /// Loads a Radiance RGBE (.hdr) file, flat or run length encoded.
+    pub fn load_hdr<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let mut reader = BufReader::new(File::open(path)?);
+
+        // Header, up to an empty line, then the resolution line.
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Err(invalid("unexpected end of header"));
+            }
+            let trimmed = line.trim();
+            if trimmed.starts_with("FORMAT=") && trimmed != "FORMAT=32-bit_rle_rgbe" {
+                return Err(invalid("unsupported pixel format"));
+            }
+            if trimmed.is_empty() {
+                break;
+            }
+        }
+        line.clear();
+        reader.read_line(&mut line)?;
+        let (height, width) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["-Y", h, "+X", w] => (
+                h.parse::<usize>().map_err(|_| invalid("bad resolution"))?,
+                w.parse::<usize>().map_err(|_| invalid("bad resolution"))?,
+            ),
+            _ => return Err(invalid("unsupported resolution line")),
+        };
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        let mut bytes = data.into_iter();
+        let mut next = || bytes.next().ok_or_else(|| invalid("truncated pixel data"));
+
+        let mut pixels = Vec::with_capacity(width * height);
+        let mut scanline = vec![[0_u8; 4]; width];
+        for _ in 0..height {
+            let header = [next()?, next()?, next()?, next()?];
+            let is_rle = (8..0x8000).contains(&width) && header[0] == 2 && header[1] == 2 && header[2] & 0x80 == 0;
+
+            if is_rle {
+                if ((header[2] as usize) << 8 | header[3] as usize) != width {
+                    return Err(invalid("scanline width mismatch"));
+                }
+                // Each channel is run length encoded separately.
+                for channel in 0..4 {
+                    let mut x = 0;
+                    while x < width {
+                        let count = next()? as usize;
+                        let (run, count) = if count > 128 { (true, count - 128) } else { (false, count) };
+                        if count == 0 || x + count > width {
+                            return Err(invalid("bad scanline run"));
+                        }
+                        let value = if run { next()? } else { 0 };
+                        for pixel in scanline[x..x + count].iter_mut() {
+                            pixel[channel] = if run { value } else { next()? };
+                        }
+                        x += count;
+                    }
+                }
+            } else {
+                scanline[0] = header;
+                for pixel in scanline.iter_mut().skip(1) {
+                    *pixel = [next()?, next()?, next()?, next()?];
+                }
+            }
+
+            pixels.extend(scanline.iter().map(|&[r, g, b, e]| {
+                if e == 0 {
+                    Color::ZERO
+                } else {
+                    let f = f64::powi(2., e as i32 - (128 + 8));
+                    Color::new(r as f64 * f, g as f64 * f, b as f64 * f)
+                }
+            }));
+        }
+
+        Ok(Self::new(width, height, pixels))
+    }
+
+    fn direction_to_uv(direction: Vec3) -> (f64, f64) {
+        let d = normalize(direction);
+        let u = 0.5 + f64::atan2(d.x(), -d.z()) / (2. * PI);
+        let v = d.y().clamp(-1., 1.).acos() / PI;
+        (u, v)
+    }
+
+    fn uv_to_direction(u: f64, v: f64) -> Vec3 {
+        let (theta, phi) = (v * PI, (u - 0.5) * 2. * PI);
+        Vec3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())
+    }
+}
+
+impl Environment for EnvironmentMap {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let (u, v) = Self::direction_to_uv(direction);
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+        self.intensity * self.pixels[i + j * self.width]
+    }
+
+    fn sample(&self, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let (u1, u2) = sampler.get_2d();
+        let ((u, v), map_pdf) = self.distribution.sample_continuous(u1, u2);
+        let sin_theta = f64::sin(v * PI);
+        if map_pdf == 0. || sin_theta == 0. {
+            return None;
+        }
+
+        // From the (u, v) density to the solid angle one.
+        let pdf = map_pdf / (2. * PI * PI * sin_theta);
+        let wi = Self::uv_to_direction(u, v);
+        Some(LightSample { wi, distance: f64::INFINITY, radiance: self.radiance(wi) / pdf, pdf })
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let (u, v) = Self::direction_to_uv(direction);
+        let sin_theta = f64::sin(v * PI);
+        if sin_theta == 0. {
+            return 0.;
+        }
+        self.distribution.pdf(u, v) / (2. * PI * PI * sin_theta)
+    }
+}
@@ -1,6 +1,6 @@
 use std::rc::Rc;
 
-use crate::{light::{HitRecord, Hittable}, math::Intervall};
+use crate::{light::{HitRecord, Hittable}, math::Intervall, render::stats::{self, Counter}};
 
 pub struct HittableList {
     objects: Vec<Rc<dyn Hittable>>,
@@ -22,15 +22,17 @@ impl HittableList {
 
 impl Hittable for HittableList {
     fn hit(&self, ray: &super::ray::Ray, ray_t: crate::math::Intervall, hit_record: &mut HitRecord) -> bool {
+        stats::count(Counter::AggregateVisits);
         let mut temp_rec = HitRecord::dummy();
         
         let mut hit_anything = false;
         let mut closest_so_far = ray_t.max;
         
-        for object in self.objects.iter(){
+        for (i, object) in self.objects.iter().enumerate(){
             let intervall = Intervall::new(ray_t.min, closest_so_far);
             if object.hit(ray, intervall, &mut temp_rec){
                 hit_anything = true;
+                temp_rec.object_id = Some(i);
                 closest_so_far = temp_rec.t;
                 *hit_record = temp_rec.clone();
 
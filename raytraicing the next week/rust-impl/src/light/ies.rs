@@ -0,0 +1,326 @@
+use std::{fmt::Display, fs, io, path::Path, rc::Rc};
+
+use crate::{
+    color::{Color, BLACK},
+    light::{
+        light_tree::LightBounds,
+        lights::{Light, LightSample},
+        material::Material,
+        ray::Ray,
+        HitRecord,
+    },
+    math::{dot, normalize, orthonormal_basis, Aabb, Point3, Sampler, Vec3},
+};
+
+#[derive(Debug)]
+pub enum IesError {
+    Io(io::Error),
+    MissingTilt,
+    UnsupportedTilt(String),
+    UnsupportedPhotometricType(i64),
+    UnexpectedEnd,
+    InvalidNumber(String),
+    InvalidAngles(&'static str),
+    InvalidCount(f64),
+}
+
+impl Display for IesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IesError::Io(e) => write!(f, "can't read the IES file: {e}"),
+            IesError::MissingTilt => write!(f, "missing TILT= line"),
+            IesError::UnsupportedTilt(t) => write!(f, "unsupported tilt: TILT={t}"),
+            IesError::UnsupportedPhotometricType(t) => write!(f, "unsupported photometric type {t}, only type C (1) is"),
+            IesError::UnexpectedEnd => write!(f, "unexpected end of file"),
+            IesError::InvalidNumber(n) => write!(f, "invalid number '{n}'"),
+            IesError::InvalidAngles(msg) => write!(f, "invalid angles: {msg}"),
+            IesError::InvalidCount(n) => write!(f, "invalid count {n}, expected an integer in [0, {}]", IesProfile::MAX_COUNT),
+        }
+    }
+}
+
+impl std::error::Error for IesError {}
+
+impl From<io::Error> for IesError {
+    fn from(e: io::Error) -> Self {
+        IesError::Io(e)
+    }
+}
+
+/// Photometric web of an IES LM-63 file (type C photometry): the luminous intensity, in
+/// candela, for vertical angles measured from the nadir and horizontal angles around it.
+#[derive(Debug)]
+pub struct IesProfile {
+    vertical_angles: Vec<f64>,
+    horizontal_angles: Vec<f64>,
+    candela: Vec<Vec<f64>>, // One row of vertical samples per horizontal angle
+    max_candela: f64,
+}
+
+impl IesProfile {
+    // Far more angles than any measured web has, so that a corrupt file doesn't allocate without bound.
+    const MAX_COUNT: usize = 100_000;
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, IesError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn parse(content: &str) -> Result<Self, IesError> {
+        // Keyword lines come first, up to the TILT line; everything after it is numbers.
+        let mut lines = content.lines();
+        let tilt = loop {
+            let line = lines.next().ok_or(IesError::MissingTilt)?.trim();
+            if let Some(tilt) = line.strip_prefix("TILT=") {
+                break tilt.trim().to_string();
+            }
+        };
+
+        let rest: Vec<&str> = lines.collect();
+        let mut tokens = rest.iter().flat_map(|l| l.split_whitespace());
+        let mut next = || -> Result<f64, IesError> {
+            let token = tokens.next().ok_or(IesError::UnexpectedEnd)?;
+            token.parse::<f64>().map_err(|_| IesError::InvalidNumber(token.to_string()))
+        };
+        let count = |n: f64| {
+            if n.is_finite() && n >= 0. && n.fract() == 0. && n <= Self::MAX_COUNT as f64 {
+                Ok(n as usize)
+            } else {
+                Err(IesError::InvalidCount(n))
+            }
+        };
+
+        match tilt.as_str() {
+            "NONE" => (),
+            "INCLUDE" => {
+                // Lamp to luminaire geometry, then the tilt angles and factors, ignored.
+                next()?;
+                let pairs = count(next()?)?;
+                for _ in 0..2 * pairs {
+                    next()?;
+                }
+            }
+            _ => return Err(IesError::UnsupportedTilt(tilt)),
+        }
+
+        let _lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let vertical_count = count(next()?)?;
+        let horizontal_count = count(next()?)?;
+        let photometric_type = next()? as i64;
+        let _units = next()?;
+        let (_width, _length, _height) = (next()?, next()?, next()?);
+        let ballast_factor = next()?;
+        let _file_generation_type = next()?;
+        let _input_watts = next()?;
+
+        if photometric_type != 1 {
+            return Err(IesError::UnsupportedPhotometricType(photometric_type));
+        }
+        if vertical_count == 0 || horizontal_count == 0 {
+            return Err(IesError::InvalidAngles("no angles"));
+        }
+
+        let vertical_angles = (0..vertical_count).map(|_| next()).collect::<Result<Vec<_>, _>>()?;
+        let horizontal_angles = (0..horizontal_count).map(|_| next()).collect::<Result<Vec<_>, _>>()?;
+        let is_sorted = |a: &[f64]| a.windows(2).all(|w| w[0] < w[1]);
+        if !is_sorted(&vertical_angles) || !is_sorted(&horizontal_angles) {
+            return Err(IesError::InvalidAngles("angles must be increasing"));
+        }
+        if vertical_angles[0] < 0. || vertical_angles[vertical_count - 1] > 180. {
+            return Err(IesError::InvalidAngles("vertical angles must be within [0, 180]"));
+        }
+        if horizontal_angles[0] != 0. || horizontal_angles[horizontal_count - 1] > 360. {
+            return Err(IesError::InvalidAngles("horizontal angles must start at 0 and end at most at 360"));
+        }
+
+        let scale = multiplier * ballast_factor;
+        let mut candela = Vec::with_capacity(horizontal_count);
+        for _ in 0..horizontal_count {
+            let row = (0..vertical_count).map(|_| next().map(|c| c * scale)).collect::<Result<Vec<_>, _>>()?;
+            candela.push(row);
+        }
+        let max_candela = candela.iter().flatten().fold(0_f64, |a, &b| a.max(b));
+
+        Ok(Self { vertical_angles, horizontal_angles, candela, max_candela })
+    }
+
+    pub fn max_candela(&self) -> f64 {
+        self.max_candela
+    }
+
+    /// Intensity in candela, angles being in degrees.
+    pub fn intensity(&self, vertical: f64, horizontal: f64) -> f64 {
+        // Unfold the horizontal symmetries: the last angle tells which part of the web is given.
+        let horizontal = horizontal.rem_euclid(360.);
+        let last = *self.horizontal_angles.last().unwrap();
+        let horizontal = if last == 0. {
+            0.
+        } else if last <= 90. {
+            let h = horizontal % 180.;
+            if h > 90. { 180. - h } else { h }
+        } else if last <= 180. {
+            if horizontal > 180. { 360. - horizontal } else { horizontal }
+        } else {
+            horizontal
+        };
+
+        let (h0, h1, th) = Self::segment(&self.horizontal_angles, horizontal);
+        let (v0, v1, tv) = Self::segment(&self.vertical_angles, vertical);
+
+        let at = |h: usize| self.candela[h][v0] * (1. - tv) + self.candela[h][v1] * tv;
+        at(h0) * (1. - th) + at(h1) * th
+    }
+
+    /// Indices around `x` in the sorted `angles`, and the interpolation factor between them.
+    fn segment(angles: &[f64], x: f64) -> (usize, usize, f64) {
+        let i = angles.partition_point(|&a| a <= x);
+        if i == 0 {
+            return (0, 0, 0.);
+        }
+        if i == angles.len() {
+            return (i - 1, i - 1, 0.);
+        }
+        (i - 1, i, (x - angles[i - 1]) / (angles[i] - angles[i - 1]))
+    }
+}
+
+/// Orientation of a photometric web: the nadir (vertical angle 0) and the reference of the
+/// horizontal angles.
+#[derive(Clone, Copy)]
+struct PhotometricFrame {
+    nadir: Vec3,
+    reference: Vec3,
+    side: Vec3,
+}
+
+impl PhotometricFrame {
+    fn new(nadir: Vec3) -> Self {
+        let nadir = normalize(nadir);
+        let (reference, side) = orthonormal_basis(nadir);
+        Self { nadir, reference, side }
+    }
+
+    /// Vertical and horizontal angles, in degrees, of the unit direction `w`.
+    fn angles(&self, w: Vec3) -> (f64, f64) {
+        let vertical = dot(w, self.nadir).clamp(-1., 1.).acos().to_degrees();
+        let horizontal = f64::atan2(dot(w, self.side), dot(w, self.reference)).to_degrees();
+        (vertical, horizontal)
+    }
+}
+
+/// Point light whose intensity follows an IES photometric web, scaled by `scale` (the
+/// candela to radiance conversion).
+pub struct IesLight {
+    position: Point3,
+    frame: PhotometricFrame,
+    profile: Rc<IesProfile>,
+    scale: Color,
+}
+
+impl IesLight {
+    /// The light is aimed at `lookat`, which gets the nadir of the web.
+    pub fn new(position: Point3, lookat: Point3, profile: Rc<IesProfile>, scale: Color) -> Self {
+        Self { position, frame: PhotometricFrame::new(lookat - position), profile, scale }
+    }
+}
+
+impl Light for IesLight {
+    fn sample_li(&self, p: Point3, _sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance_sq = to_light.length_sq();
+        let distance = distance_sq.sqrt();
+        let wi = to_light / distance;
+
+        let (vertical, horizontal) = self.frame.angles(-wi);
+        let intensity = self.profile.intensity(vertical, horizontal);
+        if intensity <= 0. {
+            return None;
+        }
+        Some(LightSample { wi, distance, radiance: intensity * self.scale / distance_sq, pdf: 0. })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let luminance = (self.scale.x() + self.scale.y() + self.scale.z()) / 3.;
+        let phi = 4. * std::f64::consts::PI * self.profile.max_candela() * luminance;
+        Some(LightBounds::omnidirectional(Aabb::from_points(self.position, self.position), phi))
+    }
+}
+
+/// Diffuse emission modulated by an IES photometric web, for emissive geometry. The web is
+/// normalized so that its peak emits `emit`.
+pub struct IesDiffuseLightMat {
+    emit: Color,
+    frame: PhotometricFrame,
+    profile: Rc<IesProfile>,
+}
+
+impl IesDiffuseLightMat {
+    pub fn new(emit: Color, nadir: Vec3, profile: Rc<IesProfile>) -> Self {
+        Self { emit, frame: PhotometricFrame::new(nadir), profile }
+    }
+}
+
+impl Material for IesDiffuseLightMat {
+    fn emitted(&self, ray_in: &Ray, rec: &HitRecord) -> Color {
+        if !rec.front_face || self.profile.max_candela() <= 0. {
+            return BLACK;
+        }
+        let (vertical, horizontal) = self.frame.angles(normalize(-ray_in.direction));
+        self.emit * (self.profile.intensity(vertical, horizontal) / self.profile.max_candela())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Two vertical angles, one horizontal angle (rotationally symmetric).
+    fn web(tilt: &str, counts: &str) -> String {
+        format!("IESNA:LM-63-2002\n[TEST] web\nTILT={tilt}\n1 1000 1 {counts} 1 1 0 0 0\n1 1 100\n0 90\n0\n200 100\n")
+    }
+
+    #[test]
+    fn parses_a_web_without_tilt() {
+        let profile = IesProfile::parse(&web("NONE", "2 1")).unwrap();
+        assert_eq!(profile.max_candela(), 200.);
+        assert_eq!(profile.intensity(0., 0.), 200.);
+        assert_eq!(profile.intensity(45., 123.), 150.);
+        assert_eq!(profile.intensity(120., 0.), 100.);
+    }
+
+    #[test]
+    fn skips_included_tilt_data() {
+        let content = web("INCLUDE", "2 1").replacen("\n1 1000", "\n1\n2\n0 90\n1 0.5\n1 1000", 1);
+        let profile = IesProfile::parse(&content).unwrap();
+        assert_eq!(profile.intensity(0., 0.), 200.);
+    }
+
+    #[test]
+    fn rejects_bad_tilt_pair_counts() {
+        for pairs in ["-1", "NaN", "inf", "1e30", "2.5"] {
+            let content = web("INCLUDE", "2 1").replacen("\n1 1000", &format!("\n1\n{pairs}\n1 1000"), 1);
+            assert!(matches!(IesProfile::parse(&content), Err(IesError::InvalidCount(_))), "{pairs} pairs");
+        }
+    }
+
+    #[test]
+    fn rejects_bad_angle_counts() {
+        for counts in ["-2 1", "2 NaN", "1e300 1", "0 1"] {
+            assert!(IesProfile::parse(&web("NONE", counts)).is_err(), "{counts} angles");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert!(matches!(IesProfile::parse("IESNA:LM-63-2002\n"), Err(IesError::MissingTilt)));
+        assert!(matches!(IesProfile::parse(&web("lamp.tlt", "2 1")), Err(IesError::UnsupportedTilt(_))));
+        assert!(matches!(IesProfile::parse(&web("NONE", "2 1").replace("200 100", "200")), Err(IesError::UnexpectedEnd)));
+        assert!(matches!(IesProfile::parse(&web("NONE", "2 1").replace("200 100", "200 x")), Err(IesError::InvalidNumber(_))));
+        assert!(matches!(IesProfile::parse(&web("NONE", "2 1").replace("\n0 90\n", "\n90 0\n")), Err(IesError::InvalidAngles(_))));
+        assert!(matches!(
+            IesProfile::parse(&web("NONE", "2 1").replacen("1 1 0 0 0", "2 1 0 0 0", 1)),
+            Err(IesError::UnsupportedPhotometricType(2))
+        ));
+    }
+}
@@ -0,0 +1,265 @@
+use std::{f64::consts::PI, rc::Rc};
+
+use crate::{
+    light::lights::Light,
+    math::{cross, dot, normalize, Aabb, Point3, Vec3},
+};
+
+/// Bounds of the power emitted by a set of lights: where it comes from (`aabb`), how much of it
+/// there is (`phi`), and in which directions, as a cone of normals of axis `w` and half angle
+/// acos(`cos_theta_o`) around which light is emitted up to acos(`cos_theta_e`) further.
+/// See "Importance Sampling of Many Lights with Adaptive Tree Splitting" (Estevez, Kulla 2018)
+/// and pbrt-v4's `LightBounds`.
+#[derive(Debug, Clone, Copy)]
+pub struct LightBounds {
+    aabb: Aabb,
+    phi: f64,
+    w: Vec3,
+    cos_theta_o: f64,
+    cos_theta_e: f64,
+}
+
+impl LightBounds {
+    pub fn new(aabb: Aabb, phi: f64, w: Vec3, cos_theta_o: f64, cos_theta_e: f64) -> Self {
+        Self { aabb, phi, w: normalize(w), cos_theta_o, cos_theta_e }
+    }
+
+    /// Bounds of a light emitting in every direction.
+    pub fn omnidirectional(aabb: Aabb, phi: f64) -> Self {
+        Self::new(aabb, phi, Vec3::new(0., 0., 1.), -1., 0.)
+    }
+
+    fn union(a: &Self, b: &Self) -> Self {
+        if a.phi == 0. {
+            return *b;
+        }
+        if b.phi == 0. {
+            return *a;
+        }
+        let (w, cos_theta_o) = Self::cone_union((a.w, a.cos_theta_o), (b.w, b.cos_theta_o));
+        Self {
+            aabb: Aabb::surrounding(&a.aabb, &b.aabb),
+            phi: a.phi + b.phi,
+            w,
+            cos_theta_o,
+            cos_theta_e: a.cos_theta_e.min(b.cos_theta_e),
+        }
+    }
+
+    /// Smallest cone containing the two direction cones.
+    fn cone_union(a: (Vec3, f64), b: (Vec3, f64)) -> (Vec3, f64) {
+        let whole_sphere = (Vec3::new(0., 0., 1.), -1.);
+        let theta_a = a.1.clamp(-1., 1.).acos();
+        let theta_b = b.1.clamp(-1., 1.).acos();
+        let theta_d = dot(a.0, b.0).clamp(-1., 1.).acos();
+
+        if (theta_d + theta_b).min(PI) <= theta_a {
+            return a;
+        }
+        if (theta_d + theta_a).min(PI) <= theta_b {
+            return b;
+        }
+
+        let theta_o = (theta_a + theta_d + theta_b) / 2.;
+        if theta_o >= PI {
+            return whole_sphere;
+        }
+
+        // Rotate a's axis toward b's one, around their common normal.
+        let theta_r = theta_o - theta_a;
+        let axis = cross(a.0, b.0);
+        if axis.near_zero() {
+            return whole_sphere;
+        }
+        let k = normalize(axis);
+        let w = a.0 * theta_r.cos() + cross(k, a.0) * theta_r.sin() + k * (dot(k, a.0) * (1. - theta_r.cos()));
+        (w, theta_o.cos())
+    }
+
+    /// Conservative estimate of the contribution of the bounded lights at `p`.
+    fn importance(&self, p: Point3) -> f64 {
+        if self.phi == 0. {
+            return 0.;
+        }
+
+        let pc = self.aabb.centroid();
+        let half_diagonal = self.aabb.diagonal().length() / 2.;
+        let distance_sq = (p - pc).length_sq().max(half_diagonal);
+
+        // Clamped differences of angles, from their sines and cosines.
+        let cos_sub_clamped = |sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64| {
+            if cos_a > cos_b { 1. } else { cos_a * cos_b + sin_a * sin_b }
+        };
+        let sin_sub_clamped = |sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64| {
+            if cos_a > cos_b { 0. } else { sin_a * cos_b - cos_a * sin_b }
+        };
+        let sin_of = |cos: f64| f64::sqrt((1. - cos * cos).max(0.));
+
+        let wi = if (p - pc).near_zero() { self.w } else { normalize(p - pc) };
+        let cos_theta_w = dot(self.w, wi);
+        let sin_theta_w = sin_of(cos_theta_w);
+
+        // Cone of directions from p toward the bounding box.
+        let radius_sq = half_diagonal * half_diagonal;
+        let cos_theta_b = if (p - pc).length_sq() < radius_sq {
+            -1.
+        } else {
+            f64::sqrt((1. - radius_sq / (p - pc).length_sq()).max(0.))
+        };
+        let sin_theta_b = sin_of(cos_theta_b);
+
+        // Smallest angle between the emission cone and the direction toward p.
+        let (sin_theta_o, cos_theta_o) = (sin_of(self.cos_theta_o), self.cos_theta_o);
+        let cos_theta_x = cos_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, cos_theta_o);
+        let sin_theta_x = sin_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, cos_theta_o);
+        let cos_theta_p = cos_sub_clamped(sin_theta_x, cos_theta_x, sin_theta_b, cos_theta_b);
+        if cos_theta_p <= self.cos_theta_e {
+            return 0.;
+        }
+
+        self.phi * cos_theta_p / distance_sq
+    }
+}
+
+enum LightNode {
+    Leaf { bounds: LightBounds, light: usize },
+    Interior { bounds: LightBounds, children: [usize; 2] },
+}
+
+impl LightNode {
+    fn bounds(&self) -> &LightBounds {
+        match self {
+            LightNode::Leaf { bounds, .. } | LightNode::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// Bounding volume hierarchy over the scene lights, picking a light with a probability
+/// proportional to its estimated contribution at the shading point. Lights at infinity are
+/// kept aside and picked uniformly.
+pub struct LightTree {
+    nodes: Vec<LightNode>,
+    infinite_lights: Vec<usize>,
+    // For each light, the branches (bit i set: right child at depth i) leading to its leaf.
+    light_paths: Vec<Option<(u64, u32)>>,
+}
+
+impl LightTree {
+    pub fn new(lights: &[Rc<dyn Light>]) -> Self {
+        let mut infinite_lights = vec![];
+        let mut bounded = vec![];
+        for (i, light) in lights.iter().enumerate() {
+            match light.bounds() {
+                Some(bounds) if bounds.phi > 0. => bounded.push((i, bounds)),
+                Some(_) => (),
+                None => infinite_lights.push(i),
+            }
+        }
+
+        let mut tree = Self { nodes: vec![], infinite_lights, light_paths: vec![None; lights.len()] };
+        if !bounded.is_empty() {
+            tree.build(&mut bounded, 0, 0);
+        }
+        tree
+    }
+
+    fn build(&mut self, lights: &mut [(usize, LightBounds)], path: u64, depth: u32) -> usize {
+        let index = self.nodes.len();
+        if lights.len() == 1 || depth == 63 {
+            let (light, bounds) = lights[0];
+            self.light_paths[light] = Some((path, depth));
+            self.nodes.push(LightNode::Leaf { bounds, light });
+            return index;
+        }
+
+        // Split at the median of the centroids along the longest axis of their bounds.
+        let centroids = lights.iter().fold(Aabb::EMPTY, |acc, (_, b)| {
+            let c = b.aabb.centroid();
+            Aabb::surrounding(&acc, &Aabb::from_points(c, c))
+        });
+        let axis = centroids.longest_axis();
+        lights.sort_by(|(_, a), (_, b)| a.aabb.centroid()[axis].total_cmp(&b.aabb.centroid()[axis]));
+        let mid = lights.len() / 2;
+
+        self.nodes.push(LightNode::Leaf { bounds: lights[0].1, light: lights[0].0 }); // Placeholder
+        let (left, right) = lights.split_at_mut(mid);
+        let left = self.build(left, path, depth + 1);
+        let right = self.build(right, path | (1 << depth), depth + 1);
+
+        let bounds = LightBounds::union(self.nodes[left].bounds(), self.nodes[right].bounds());
+        self.nodes[index] = LightNode::Interior { bounds, children: [left, right] };
+        index
+    }
+
+    fn infinite_probability(&self) -> f64 {
+        let bounded = if self.nodes.is_empty() { 0. } else { 1. };
+        let infinite = self.infinite_lights.len() as f64;
+        if infinite == 0. { 0. } else { infinite / (infinite + bounded) }
+    }
+
+    /// Picks a light for the shading point `p`, returns its index with its probability.
+    pub fn sample(&self, p: Point3, u: f64) -> Option<(usize, f64)> {
+        let p_infinite = self.infinite_probability();
+        if u < p_infinite {
+            let i = ((u / p_infinite) * self.infinite_lights.len() as f64) as usize;
+            let i = i.min(self.infinite_lights.len() - 1);
+            return Some((self.infinite_lights[i], p_infinite / self.infinite_lights.len() as f64));
+        }
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut u = ((u - p_infinite) / (1. - p_infinite)).min(1. - f64::EPSILON);
+        let mut pmf = 1. - p_infinite;
+        let mut node = 0;
+        loop {
+            match &self.nodes[node] {
+                LightNode::Leaf { bounds, light } => {
+                    return (bounds.importance(p) > 0.).then_some((*light, pmf));
+                }
+                LightNode::Interior { children, .. } => {
+                    let i0 = self.nodes[children[0]].bounds().importance(p);
+                    let i1 = self.nodes[children[1]].bounds().importance(p);
+                    if i0 == 0. && i1 == 0. {
+                        return None;
+                    }
+
+                    // Reuse the random number for the next level.
+                    let p0 = i0 / (i0 + i1);
+                    if u < p0 {
+                        node = children[0];
+                        pmf *= p0;
+                        u /= p0;
+                    } else {
+                        node = children[1];
+                        pmf *= 1. - p0;
+                        u = ((u - p0) / (1. - p0)).min(1. - f64::EPSILON);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Probability with which `sample` picks the light `light` from `p`.
+    pub fn pmf(&self, p: Point3, light: usize) -> f64 {
+        if self.infinite_lights.contains(&light) {
+            return self.infinite_probability() / self.infinite_lights.len() as f64;
+        }
+        let Some((path, depth)) = self.light_paths.get(light).copied().flatten() else { return 0. };
+
+        let mut pmf = 1. - self.infinite_probability();
+        let mut node = 0;
+        for level in 0..depth {
+            let LightNode::Interior { children, .. } = &self.nodes[node] else { break };
+            let i0 = self.nodes[children[0]].bounds().importance(p);
+            let i1 = self.nodes[children[1]].bounds().importance(p);
+            if i0 == 0. && i1 == 0. {
+                return 0.;
+            }
+            let right = path & (1 << level) != 0;
+            pmf *= if right { i1 } else { i0 } / (i0 + i1);
+            node = children[right as usize];
+        }
+        if self.nodes[node].bounds().importance(p) > 0. { pmf } else { 0. }
+    }
+}
@@ -0,0 +1,256 @@
+use std::f64::consts::PI;
+
+use crate::{
+    color::{Color, BLACK},
+    light::light_tree::LightBounds,
+    math::{deg_to_rad, dot, normalize, random_in_cone, Aabb, Point3, Sampler, Vec3},
+};
+
+pub struct LightSample {
+    pub wi: Vec3,          // Unit direction from the shading point toward the light
+    pub distance: f64,     // Distance to the light, infinite for directional lights
+    pub radiance: Color,   // Incident radiance, already divided by the sampling pdf
+    pub pdf: f64,          // Solid angle density of `wi`, zero for delta lights
+}
+
+/// Lights sampled explicitly from a shading point (next event estimation).
+pub trait Light {
+    fn sample_li(&self, p: Point3, sampler: &mut dyn Sampler) -> Option<LightSample>;
+
+    /// Radiance carried by a ray escaping the scene in `direction`, for lights at infinity.
+    #[allow(unused)]
+    fn le(&self, direction: Vec3) -> Color {
+        BLACK
+    }
+
+    /// Solid angle density with which `sample_li` would pick `wi` from `p`,
+    /// for multiple importance sampling. Delta lights can't be hit and return zero.
+    #[allow(unused)]
+    fn pdf_li(&self, p: Point3, wi: Vec3) -> f64 {
+        0.
+    }
+
+    /// Spatial and directional bounds of the emitted power, used to build the light tree.
+    /// Lights at infinity have none.
+    fn bounds(&self) -> Option<LightBounds> {
+        None
+    }
+}
+
+pub struct PointLight {
+    position: Point3,
+    intensity: Color,
+}
+
+impl PointLight {
+    pub fn new(position: Point3, intensity: Color) -> Self {
+        Self { position, intensity }
+    }
+}
+
+impl Light for PointLight {
+    fn sample_li(&self, p: Point3, _sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance_sq = to_light.length_sq();
+        let distance = distance_sq.sqrt();
+
+        Some(LightSample {
+            wi: to_light / distance,
+            distance,
+            radiance: self.intensity / distance_sq,
+            pdf: 0.,
+        })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let phi = 4. * PI * luminance(self.intensity);
+        Some(LightBounds::omnidirectional(Aabb::from_points(self.position, self.position), phi))
+    }
+}
+
+pub struct SpotLight {
+    position: Point3,
+    direction: Vec3,
+    intensity: Color,
+    cos_total_width: f64,
+    cos_falloff_start: f64,
+}
+
+impl SpotLight {
+    /// `total_width` is the cone half angle in degrees, the intensity smoothly
+    /// decreases from `falloff_start` (also in degrees) to the cone border.
+    pub fn new(position: Point3, lookat: Point3, intensity: Color, total_width: f64, falloff_start: f64) -> Self {
+        Self {
+            position,
+            direction: normalize(lookat - position),
+            intensity,
+            cos_total_width: f64::cos(deg_to_rad(total_width)),
+            cos_falloff_start: f64::cos(deg_to_rad(falloff_start.min(total_width))),
+        }
+    }
+
+    fn falloff(&self, w: Vec3) -> f64 {
+        let cos_theta = dot(w, self.direction);
+        if cos_theta < self.cos_total_width {
+            return 0.;
+        }
+        if cos_theta >= self.cos_falloff_start {
+            return 1.;
+        }
+        let delta = (cos_theta - self.cos_total_width) / (self.cos_falloff_start - self.cos_total_width);
+        delta * delta * (3. - 2. * delta)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample_li(&self, p: Point3, _sampler: &mut dyn Sampler) -> Option<LightSample> {
+        let to_light = self.position - p;
+        let distance_sq = to_light.length_sq();
+        let distance = distance_sq.sqrt();
+        let wi = to_light / distance;
+
+        let falloff = self.falloff(-wi);
+        if falloff <= 0. {
+            return None;
+        }
+        Some(LightSample { wi, distance, radiance: falloff * self.intensity / distance_sq, pdf: 0. })
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        // Power of a constant cone plus the smoothstep falloff, approximated as linear.
+        let solid_angle = 2. * PI * ((1. - self.cos_falloff_start) + (self.cos_falloff_start - self.cos_total_width) / 2.);
+        Some(LightBounds::new(
+            Aabb::from_points(self.position, self.position),
+            luminance(self.intensity) * solid_angle,
+            self.direction,
+            self.cos_falloff_start,
+            f64::cos(self.cos_total_width.acos() - self.cos_falloff_start.acos()),
+        ))
+    }
+}
+
+pub struct DirectionalLight {
+    direction: Vec3,
+    radiance: Color,
+}
+
+impl DirectionalLight {
+    /// `direction` is the direction the light travels in.
+    pub fn new(direction: Vec3, radiance: Color) -> Self {
+        Self { direction: normalize(direction), radiance }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample_li(&self, _p: Point3, _sampler: &mut dyn Sampler) -> Option<LightSample> {
+        Some(LightSample { wi: -self.direction, distance: f64::INFINITY, radiance: self.radiance, pdf: 0. })
+    }
+}
+
+/// A disk light at infinity, such as the sun, seen under a small solid angle.
+pub struct SunLight {
+    direction: Vec3,
+    radiance: Color,
+    cos_max: f64,
+}
+
+impl SunLight {
+    /// `direction` points toward the sun, `angular_radius` is in degrees
+    /// (about 0.265 for the sun seen from earth).
+    pub fn new(direction: Vec3, radiance: Color, angular_radius: f64) -> Self {
+        Self { direction: normalize(direction), radiance, cos_max: f64::cos(deg_to_rad(angular_radius)) }
+    }
+
+    fn solid_angle(&self) -> f64 {
+        2. * PI * (1. - self.cos_max)
+    }
+}
+
+impl Light for SunLight {
+    fn sample_li(&self, _p: Point3, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        // Uniform sampling of the cone subtended by the disk, pdf = 1 / solid_angle.
+        Some(LightSample {
+            wi: random_in_cone(sampler, self.direction, self.cos_max),
+            distance: f64::INFINITY,
+            radiance: self.radiance * self.solid_angle(),
+            pdf: self.solid_angle().recip(),
+        })
+    }
+
+    fn le(&self, direction: Vec3) -> Color {
+        if dot(normalize(direction), self.direction) >= self.cos_max {
+            self.radiance
+        } else {
+            BLACK
+        }
+    }
+
+    fn pdf_li(&self, _p: Point3, wi: Vec3) -> f64 {
+        if dot(normalize(wi), self.direction) >= self.cos_max {
+            self.solid_angle().recip()
+        } else {
+            0.
+        }
+    }
+}
+
+/// The light emitted by a sphere with a uniform diffuse emission, to be paired with the
+/// matching `Sphere` (see `Scene::push_emissive_sphere`).
+pub struct SphereLight {
+    center: Point3,
+    radius: f64,
+    radiance: Color,
+}
+
+impl SphereLight {
+    pub fn new(center: Point3, radius: f64, radiance: Color) -> Self {
+        Self { center, radius, radiance }
+    }
+
+    fn cos_max(&self, p: Point3) -> Option<f64> {
+        let distance_sq = (self.center - p).length_sq();
+        let radius_sq = self.radius * self.radius;
+        if distance_sq <= radius_sq {
+            return None;
+        }
+        Some(f64::sqrt(1. - radius_sq / distance_sq))
+    }
+}
+
+impl Light for SphereLight {
+    fn sample_li(&self, p: Point3, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        // Uniform sampling of the cone subtended by the sphere.
+        let cos_max = self.cos_max(p)?;
+        let to_center = self.center - p;
+        let wi = random_in_cone(sampler, normalize(to_center), cos_max);
+
+        // Distance to the first intersection with the sphere.
+        let b = dot(wi, to_center);
+        let c = to_center.length_sq() - self.radius * self.radius;
+        let distance = b - f64::sqrt((b * b - c).max(0.));
+
+        let pdf = (2. * PI * (1. - cos_max)).recip();
+        Some(LightSample { wi, distance, radiance: self.radiance / pdf, pdf })
+    }
+
+    fn pdf_li(&self, p: Point3, wi: Vec3) -> f64 {
+        let Some(cos_max) = self.cos_max(p) else { return 0. };
+        if dot(normalize(wi), normalize(self.center - p)) < cos_max {
+            return 0.;
+        }
+        (2. * PI * (1. - cos_max)).recip()
+    }
+
+    fn bounds(&self) -> Option<LightBounds> {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        let area = 4. * PI * self.radius * self.radius;
+        Some(LightBounds::omnidirectional(
+            Aabb::from_points(self.center - r, self.center + r),
+            PI * area * luminance(self.radiance),
+        ))
+    }
+}
+
+fn luminance(c: Color) -> f64 {
+    (c.x() + c.y() + c.z()) / 3.
+}
@@ -1,20 +1,65 @@
+use std::{f64::consts::PI, rc::Rc};
+
 use crate::{
-    color::Color,
-    light::{HitRecord, ray::Ray},
-    math::{dot, normalize, random_unit_vec, reflect, refract},
+    color::{Color, BLACK},
+    light::{HitRecord, medium::{HomogeneousMedium, MediumSample}, ray::Ray, texture::{SolidColor, Texture}},
+    math::{dot, lerp, normalize, random_unit_vec, reflect, refract, Sampler, Vec3},
 };
 
+pub struct ScatterRecord {
+    pub attenuation: Color,
+    pub scattered: Ray,
+    pub skip_pdf: bool, // Specular bounce, can't be lit by sampling the lights
+}
+
+impl ScatterRecord {
+    pub fn new() -> Self {
+        Self { attenuation: Color::ZERO, scattered: Ray::new(Vec3::ZERO, Vec3::ZERO), skip_pdf: true }
+    }
+}
+
+impl Default for ScatterRecord {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub trait Material {
     #[allow(unused)]
     fn scatter(
         &self,
         ray_in: &Ray,
         rec: &mut HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
     ) -> bool {
         false
     }
+
+    /// Evaluates the BRDF times the cosine term for light coming from `wi`, used when
+    /// sampling lights explicitly. Specular materials can't be lit that way and return black,
+    /// their `scatter` sets `skip_pdf`.
+    #[allow(unused)]
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, wi: Vec3) -> Color {
+        BLACK
+    }
+
+    /// Solid angle density with which the non specular part of `scatter` picks `wi`.
+    #[allow(unused)]
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, wi: Vec3) -> f64 {
+        0.
+    }
+
+    #[allow(unused)]
+    fn emitted(&self, ray_in: &Ray, rec: &HitRecord) -> Color {
+        BLACK
+    }
+
+    /// Overall reflectance color at the hit, for the albedo AOV. Not used for shading.
+    #[allow(unused)]
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        BLACK
+    }
 }
 
 pub struct NoMat{}
@@ -24,11 +69,21 @@ impl Material for NoMat {
             &self,
             ray_in: &Ray,
             rec: &mut HitRecord,
-            attenuation: &mut Color,
-            scattered: &mut Ray,
+            srec: &mut ScatterRecord,
+            sampler: &mut dyn Sampler,
         ) -> bool {
         let mat = LambertianMat{albedo:Color::new(0.95,0.,1.)};
-        mat.scatter(ray_in, rec, attenuation, scattered)
+        mat.scatter(ray_in, rec, srec, sampler)
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, wi: Vec3) -> Color {
+        let mat = LambertianMat{albedo:Color::new(0.95,0.,1.)};
+        mat.eval(ray_in, rec, wi)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, wi: Vec3) -> f64 {
+        let mat = LambertianMat{albedo:Color::new(0.95,0.,1.)};
+        mat.scattering_pdf(ray_in, rec, wi)
     }
 }
 
@@ -47,19 +102,48 @@ impl Material for LambertianMat {
         &self,
         _ray_in: &Ray,
         rec: &mut HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
     ) -> bool {
-        let mut scatter_dir = rec.normal + random_unit_vec();
+        let mut scatter_dir = rec.normal + random_unit_vec(sampler);
 
         if scatter_dir.near_zero() {
             scatter_dir = rec.normal;
         }
 
-        *scattered = Ray::new(rec.p, scatter_dir);
-        *attenuation = self.albedo;
+        srec.scattered = Ray::new(rec.p, scatter_dir);
+        srec.attenuation = self.albedo;
+        srec.skip_pdf = false;
         true
     }
+
+    fn eval(&self, _ray_in: &Ray, rec: &HitRecord, wi: Vec3) -> Color {
+        self.albedo * (dot(rec.normal, wi).max(0.) / PI)
+    }
+
+    fn scattering_pdf(&self, _ray_in: &Ray, rec: &HitRecord, wi: Vec3) -> f64 {
+        dot(rec.normal, normalize(wi)).max(0.) / PI
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+}
+
+pub struct DiffuseLightMat {
+    emit: Color,
+}
+
+impl DiffuseLightMat {
+    pub fn new(emit: Color) -> Self {
+        Self { emit }
+    }
+}
+
+impl Material for DiffuseLightMat {
+    fn emitted(&self, _ray_in: &Ray, rec: &HitRecord) -> Color {
+        if rec.front_face { self.emit } else { BLACK }
+    }
 }
 
 pub struct MetalMat {
@@ -78,14 +162,19 @@ impl Material for MetalMat {
         &self,
         ray_in: &Ray,
         rec: &mut HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
     ) -> bool {
         let mut reflected = reflect(ray_in.direction, rec.normal);
-        reflected = normalize(reflected) + (self.fuzz * random_unit_vec());
-        *scattered = Ray::new(rec.p, reflected);
-        *attenuation = self.albedo;
-        dot(scattered.direction, rec.normal) > 0.
+        reflected = normalize(reflected) + (self.fuzz * random_unit_vec(sampler));
+        srec.scattered = Ray::new(rec.p, reflected);
+        srec.attenuation = self.albedo;
+        srec.skip_pdf = true;
+        dot(srec.scattered.direction, rec.normal) > 0.
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.albedo
     }
 }
 
@@ -104,10 +193,10 @@ impl Material for DielectricMat {
         &self,
         ray_in: &Ray,
         rec: &mut HitRecord,
-        attenuation: &mut Color,
-        scattered: &mut Ray,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
     ) -> bool {
-        *attenuation = Color::new(1., 1., 1.);
+        srec.attenuation = Color::new(1., 1., 1.);
         let ri = if rec.front_face {
             self.refraction_index.recip()
         } else {
@@ -119,21 +208,341 @@ impl Material for DielectricMat {
         let sin_theta = f64::sqrt(1. - cos_theta * cos_theta);
 
         let cannot_refract = ri * sin_theta > 1.;
-        let direction = if cannot_refract || self.reflectance(cos_theta, ri) > rand::random() {
+        let direction = if cannot_refract || Self::reflectance(cos_theta, ri) > sampler.get_1d() {
             reflect(unit_dir, rec.normal)
         } else {
             refract(unit_dir, rec.normal, ri)
         };
 
-        *scattered = Ray::new(rec.p, direction);
+        srec.scattered = Ray::new(rec.p, direction);
+        srec.skip_pdf = true;
         true
     }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        Color::new(1., 1., 1.)
+    }
 }
 
 impl DielectricMat {
-    fn reflectance(&self, cosine: f64, refraction_index: f64) -> f64 {
+    // Use Schlick's approximation for reflectance.
+    fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
         let r0 = (1. - refraction_index) / (1. + refraction_index);
         let r0 = r0*r0;
         r0 + (1.-r0)*f64::powi(1.-cosine, 5)
     }
 }
+
+/// Picks one of two materials per scatter event, `b` being chosen with
+/// probability `weight` (the mean of the texture's channels).
+pub struct MixMat {
+    a: Rc<dyn Material>,
+    b: Rc<dyn Material>,
+    weight: Rc<dyn Texture>,
+}
+
+impl MixMat {
+    pub fn new(a: Rc<dyn Material>, b: Rc<dyn Material>, weight: f64) -> Self {
+        Self::from_texture(a, b, Rc::new(SolidColor::new(Color::new(weight, weight, weight))))
+    }
+
+    pub fn from_texture(a: Rc<dyn Material>, b: Rc<dyn Material>, weight: Rc<dyn Texture>) -> Self {
+        Self { a, b, weight }
+    }
+}
+
+impl Material for MixMat {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &mut HitRecord,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let w = self.weight.value(rec.u, rec.v, rec.p);
+        let w = (w.x() + w.y() + w.z()) / 3.;
+
+        if sampler.get_1d() < w {
+            self.b.scatter(ray_in, rec, srec, sampler)
+        } else {
+            self.a.scatter(ray_in, rec, srec, sampler)
+        }
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, wi: Vec3) -> Color {
+        let w = self.weight.value(rec.u, rec.v, rec.p);
+        let w = (w.x() + w.y() + w.z()) / 3.;
+
+        lerp(self.a.eval(ray_in, rec, wi), self.b.eval(ray_in, rec, wi), w)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, wi: Vec3) -> f64 {
+        let w = self.weight.value(rec.u, rec.v, rec.p);
+        let w = (w.x() + w.y() + w.z()) / 3.;
+
+        lerp(self.a.scattering_pdf(ray_in, rec, wi), self.b.scattering_pdf(ray_in, rec, wi), w)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        let w = self.weight.value(rec.u, rec.v, rec.p);
+        let w = (w.x() + w.y() + w.z()) / 3.;
+
+        lerp(self.a.albedo(rec), self.b.albedo(rec), w)
+    }
+}
+
+/// A clear dielectric coating (varnish, clear coat...) over an arbitrary base material.
+/// Light is either reflected at the coating interface, following Fresnel, or
+/// transmitted to the base and back out through the coating.
+///
+/// This is an approximation, not a physically based layered BSDF: the base sees the incoming
+/// direction refracted by the coating, but the outgoing direction is taken as is, without
+/// refraction on the way out nor total internal reflection, only dimmed by the Fresnel
+/// transmittance. There are no inter-reflections between the coating and the base either.
+pub struct LayeredMat {
+    coat_refraction_index: f64,
+    base: Rc<dyn Material>,
+}
+
+impl LayeredMat {
+    pub fn new(coat_refraction_index: f64, base: Rc<dyn Material>) -> Self {
+        Self { coat_refraction_index, base }
+    }
+}
+
+impl Material for LayeredMat {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &mut HitRecord,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        if !rec.front_face {
+            return self.base.scatter(ray_in, rec, srec, sampler);
+        }
+
+        let ri = self.coat_refraction_index.recip();
+        let unit_dir = normalize(ray_in.direction);
+        let cos_in = dot(-unit_dir, rec.normal).min(1.0);
+
+        if DielectricMat::reflectance(cos_in, ri) > sampler.get_1d() {
+            srec.attenuation = Color::new(1., 1., 1.);
+            srec.scattered = Ray::new(rec.p, reflect(unit_dir, rec.normal));
+            srec.skip_pdf = true;
+            return true;
+        }
+
+        // The base is lit by the ray refracted through the coating.
+        let refracted = Ray::new(ray_in.origine, refract(unit_dir, rec.normal, ri));
+        if !self.base.scatter(&refracted, rec, srec, sampler) {
+            return false;
+        }
+
+        // Light leaving the base is partially reflected back by the coating.
+        let cos_out = dot(normalize(srec.scattered.direction), rec.normal).clamp(0., 1.);
+        srec.attenuation = srec.attenuation * (1. - DielectricMat::reflectance(cos_out, ri));
+        true
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, wi: Vec3) -> Color {
+        if !rec.front_face {
+            return self.base.eval(ray_in, rec, wi);
+        }
+
+        let ri = self.coat_refraction_index.recip();
+        let unit_dir = normalize(ray_in.direction);
+        let cos_in = dot(-unit_dir, rec.normal).min(1.0);
+        let cos_out = dot(wi, rec.normal).clamp(0., 1.);
+        let transmitted = (1. - DielectricMat::reflectance(cos_in, ri)) * (1. - DielectricMat::reflectance(cos_out, ri));
+
+        let refracted = Ray::new(ray_in.origine, refract(unit_dir, rec.normal, ri));
+        transmitted * self.base.eval(&refracted, rec, wi)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, wi: Vec3) -> f64 {
+        if !rec.front_face {
+            return self.base.scattering_pdf(ray_in, rec, wi);
+        }
+
+        let ri = self.coat_refraction_index.recip();
+        let unit_dir = normalize(ray_in.direction);
+        let cos_in = dot(-unit_dir, rec.normal).min(1.0);
+
+        let refracted = Ray::new(ray_in.origine, refract(unit_dir, rec.normal, ri));
+        (1. - DielectricMat::reflectance(cos_in, ri)) * self.base.scattering_pdf(&refracted, rec, wi)
+    }
+
+    // The coating is clear, what shows is the base.
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base.albedo(rec)
+    }
+}
+
+/// Wraps a material and perturbs the shading normal with a tangent-space normal map,
+/// whose RGB channels encode the (tangent, bitangent, normal) components in [0,1].
+pub struct NormalMapMat {
+    base: Rc<dyn Material>,
+    normal_map: Rc<dyn Texture>,
+}
+
+impl NormalMapMat {
+    pub fn new(base: Rc<dyn Material>, normal_map: Rc<dyn Texture>) -> Self {
+        Self { base, normal_map }
+    }
+}
+
+impl Material for NormalMapMat {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &mut HitRecord,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let c = self.normal_map.value(rec.u, rec.v, rec.p);
+        let local = 2. * c - Color::new(1., 1., 1.);
+        let normal = local.x() * rec.tangent + local.y() * rec.bitangent + local.z() * rec.normal;
+
+        if !normal.near_zero() {
+            let tangent = rec.tangent;
+            rec.normal = normalize(normal);
+            rec.set_tangent(tangent);
+        }
+        self.base.scatter(ray_in, rec, srec, sampler)
+    }
+
+    // The shading frame has already been perturbed by `scatter`.
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, wi: Vec3) -> Color {
+        self.base.eval(ray_in, rec, wi)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, wi: Vec3) -> f64 {
+        self.base.scattering_pdf(ray_in, rec, wi)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base.albedo(rec)
+    }
+}
+
+/// Wraps a material and perturbs the shading normal from the gradient of a height texture.
+pub struct BumpMapMat {
+    base: Rc<dyn Material>,
+    height: Rc<dyn Texture>,
+    scale: f64,
+}
+
+impl BumpMapMat {
+    const DELTA: f64 = 1e-3;
+
+    pub fn new(base: Rc<dyn Material>, height: Rc<dyn Texture>, scale: f64) -> Self {
+        Self { base, height, scale }
+    }
+
+    fn height_at(&self, u: f64, v: f64, p: Vec3) -> f64 {
+        let h = self.height.value(u, v, p);
+        (h.x() + h.y() + h.z()) / 3.
+    }
+}
+
+impl Material for BumpMapMat {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &mut HitRecord,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        // Forward differences, stepping both in uv (image textures) and in space (solid textures).
+        let d = Self::DELTA;
+        let h = self.height_at(rec.u, rec.v, rec.p);
+        let dh_du = (self.height_at(rec.u + d, rec.v, rec.p + d * rec.tangent) - h) / d;
+        let dh_dv = (self.height_at(rec.u, rec.v + d, rec.p + d * rec.bitangent) - h) / d;
+
+        let normal = rec.normal - self.scale * (dh_du * rec.tangent + dh_dv * rec.bitangent);
+        if !normal.near_zero() {
+            let tangent = rec.tangent;
+            rec.normal = normalize(normal);
+            rec.set_tangent(tangent);
+        }
+        self.base.scatter(ray_in, rec, srec, sampler)
+    }
+
+    // The shading frame has already been perturbed by `scatter`.
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, wi: Vec3) -> Color {
+        self.base.eval(ray_in, rec, wi)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, wi: Vec3) -> f64 {
+        self.base.scattering_pdf(ray_in, rec, wi)
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        self.base.albedo(rec)
+    }
+}
+
+/// Random walk subsurface scattering (skin, wax, marble...) inside a closed surface.
+/// Rays enter through a dielectric boundary, then walk through a homogeneous medium
+/// until they leave through the boundary again, so it needs a generous `max_depth`.
+pub struct SubsurfaceMat {
+    refraction_index: f64,
+    medium: HomogeneousMedium,
+}
+
+impl SubsurfaceMat {
+    pub fn new(refraction_index: f64, mean_free_path: Color, albedo: Color) -> Self {
+        Self { refraction_index, medium: HomogeneousMedium::from_mean_free_path(mean_free_path, albedo) }
+    }
+}
+
+impl Material for SubsurfaceMat {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &mut HitRecord,
+        srec: &mut ScatterRecord,
+        sampler: &mut dyn Sampler,
+    ) -> bool {
+        let unit_dir = normalize(ray_in.direction);
+
+        srec.attenuation = Color::new(1., 1., 1.);
+        if !rec.front_face {
+            // The ray travelled inside the medium up to this boundary hit.
+            let distance = rec.t * ray_in.direction.length();
+            match self.medium.sample(distance, sampler) {
+                MediumSample::Scatter { distance, weight } => {
+                    srec.attenuation = weight;
+                    srec.scattered = Ray::new(ray_in.origine + distance * unit_dir, random_unit_vec(sampler));
+                    srec.skip_pdf = true;
+                    return true;
+                }
+                MediumSample::Pass { weight } => srec.attenuation = weight,
+            }
+        }
+
+        let ri = if rec.front_face {
+            self.refraction_index.recip()
+        } else {
+            self.refraction_index
+        };
+        let cos_theta = dot(-unit_dir, rec.normal).min(1.0);
+        let sin_theta = f64::sqrt(1. - cos_theta * cos_theta);
+
+        let cannot_refract = ri * sin_theta > 1.;
+        let direction = if cannot_refract || DielectricMat::reflectance(cos_theta, ri) > sampler.get_1d() {
+            reflect(unit_dir, rec.normal)
+        } else {
+            refract(unit_dir, rec.normal, ri)
+        };
+
+        srec.scattered = Ray::new(rec.p, direction);
+        srec.skip_pdf = true;
+        true
+    }
+
+    fn albedo(&self, _rec: &HitRecord) -> Color {
+        self.medium.albedo()
+    }
+}
@@ -0,0 +1,66 @@
+use crate::{color::Color, math::Sampler};
+
+pub enum MediumSample {
+    /// The ray is scattered inside the medium, after travelling `distance`.
+    Scatter { distance: f64, weight: Color },
+    /// The ray crosses the whole medium up to the next boundary.
+    Pass { weight: Color },
+}
+
+/// Homogeneous participating medium with a per channel extinction and single scattering albedo.
+pub struct HomogeneousMedium {
+    sigma_t: Color,
+    albedo: Color,
+}
+
+impl HomogeneousMedium {
+    // Shortest mean free path, so that the extinction stays finite.
+    const MIN_MEAN_FREE_PATH: f64 = 1e-6;
+
+    /// Negative or NaN extinctions are taken as 0, too large ones are clamped, as both would
+    /// give NaN throughputs.
+    pub fn new(sigma_t: Color, albedo: Color) -> Self {
+        let clamp = |sigma: f64| if sigma >= 0. { sigma.min(Self::MIN_MEAN_FREE_PATH.recip()) } else { 0. };
+        let sigma_t = Color::new(clamp(sigma_t.x()), clamp(sigma_t.y()), clamp(sigma_t.z()));
+        Self { sigma_t, albedo }
+    }
+
+    /// Mean free paths that aren't positive are taken as the shortest one.
+    pub fn from_mean_free_path(mean_free_path: Color, albedo: Color) -> Self {
+        let sigma = |mfp: f64| mfp.max(Self::MIN_MEAN_FREE_PATH).recip();
+        let sigma_t = Color::new(sigma(mean_free_path.x()), sigma(mean_free_path.y()), sigma(mean_free_path.z()));
+        Self::new(sigma_t, albedo)
+    }
+
+    pub fn albedo(&self) -> Color {
+        self.albedo
+    }
+
+    pub fn transmittance(&self, distance: f64) -> Color {
+        Color::new(
+            f64::exp(-self.sigma_t.x() * distance),
+            f64::exp(-self.sigma_t.y() * distance),
+            f64::exp(-self.sigma_t.z() * distance),
+        )
+    }
+
+    /// Samples a free flight distance, using a channel picked uniformly, along a segment of
+    /// length `max_distance`. The returned weight is the throughput divided by the pdf averaged
+    /// over the three channels (one sample MIS), so chromatic media stay unbiased.
+    pub fn sample(&self, max_distance: f64, sampler: &mut dyn Sampler) -> MediumSample {
+        let (u1, u2) = sampler.get_2d();
+        let channel = (u1 * 3.) as usize % 3;
+        let distance = -f64::ln(1. - u2) / self.sigma_t[channel];
+
+        if distance < max_distance {
+            let tr = self.transmittance(distance);
+            let density = self.sigma_t * tr;
+            let pdf = (density.x() + density.y() + density.z()) / 3.;
+            MediumSample::Scatter { distance, weight: self.albedo * density / pdf }
+        } else {
+            let tr = self.transmittance(max_distance);
+            let pdf = (tr.x() + tr.y() + tr.z()) / 3.;
+            MediumSample::Pass { weight: tr / pdf }
+        }
+    }
+}
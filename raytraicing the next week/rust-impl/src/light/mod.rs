@@ -1,26 +1,39 @@
 use std::rc::Rc;
 
+pub mod environment_map;
 pub mod hittable_list;
+pub mod light_tree;
+pub mod ies;
+pub mod lights;
 pub mod material;
+pub mod medium;
 pub mod ray;
+pub mod sky;
+pub mod texture;
 
 use crate::{
     light::{material::{Material, NoMat}, ray::Ray},
-    math::{dot, Intervall, Point3, Vec3},
+    math::{cross, dot, normalize, orthonormal_basis, Intervall, Point3, Vec3},
 };
 
 #[derive(Clone)]
 pub struct HitRecord {
     pub p: Point3,
     pub normal: Vec3,
+    pub tangent: Vec3,   // Shading frame, follows the direction of increasing u
+    pub bitangent: Vec3, // Shading frame, follows the direction of increasing v
     pub mat: Rc<dyn Material>,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
+    pub light_id: Option<usize>, // Index in the scene lights when the surface is also sampled as a light
+    pub object_id: Option<usize>, // Index in the world list of the object hit
 }
 
 impl HitRecord {
     pub fn dummy()->Self{
-        Self { p: Point3::ZERO, normal: Vec3::ZERO, mat: Rc::new(NoMat{}), t: 0., front_face: false }
+        Self { p: Point3::ZERO, normal: Vec3::ZERO, tangent: Vec3::ZERO, bitangent: Vec3::ZERO, mat: Rc::new(NoMat{}), t: 0., u: 0., v: 0., front_face: false, light_id: None, object_id: None }
     }
 
     pub fn set_face_normal(&mut self, ray: Ray, out_normal: Vec3) {
@@ -31,6 +44,17 @@ impl HitRecord {
             -out_normal
         };
     }
+
+    /// Builds the tangent frame around `normal` from a surface tangent (dp/du).
+    /// Must be called after `set_face_normal`.
+    pub fn set_tangent(&mut self, out_tangent: Vec3) {
+        let mut tangent = out_tangent - dot(out_tangent, self.normal) * self.normal;
+        if tangent.near_zero() {
+            tangent = orthonormal_basis(self.normal).0;
+        }
+        self.tangent = normalize(tangent);
+        self.bitangent = cross(self.normal, self.tangent);
+    }
 }
 
 pub trait Hittable {
@@ -0,0 +1,193 @@
+use std::f64::consts::PI;
+
+use crate::{
+    color::{Color, WHITE},
+    light::lights::{LightSample, SunLight},
+    math::{deg_to_rad, dot, lerp, normalize, Sampler, Vec3},
+};
+
+/// Radiance coming from the background, for rays escaping the scene.
+pub trait Environment {
+    fn radiance(&self, direction: Vec3) -> Color;
+
+    /// Samples a direction toward the environment, for environments worth sampling explicitly.
+    #[allow(unused)]
+    fn sample(&self, sampler: &mut dyn Sampler) -> Option<LightSample> {
+        None
+    }
+
+    /// Solid angle density with which `sample` would pick `direction`.
+    #[allow(unused)]
+    fn pdf(&self, direction: Vec3) -> f64 {
+        0.
+    }
+}
+
+/// The classic white to blue vertical gradient.
+pub struct GradientSky {
+    pub horizon: Color,
+    pub zenith: Color,
+}
+
+impl Default for GradientSky {
+    fn default() -> Self {
+        Self { horizon: WHITE, zenith: Color::new(0.5, 0.7, 1.0) }
+    }
+}
+
+impl Environment for GradientSky {
+    fn radiance(&self, direction: Vec3) -> Color {
+        let direction = normalize(direction);
+        let a = 0.5 * (direction.y() + 1.);
+        lerp(self.horizon, self.zenith, a)
+    }
+}
+
+/// Analytic daylight model from Preetham, Shirley and Smits, "A Practical Analytic Model
+/// for Daylight" (1999). The sky is clear, parameterized by the sun position and the turbidity
+/// (2 for a very clear sky, around 10 for a hazy one). Y is up and the azimuth is measured
+/// from -Z toward +X.
+pub struct PreethamSky {
+    sun_direction: Vec3,
+    turbidity: f64,
+    perez_lum: [f64; 5],
+    perez_x: [f64; 5],
+    perez_y: [f64; 5],
+    zenith: (f64, f64, f64), // Zenith luminance (kcd/m²) and chromaticity x, y
+    luminance_scale: f64,
+}
+
+impl PreethamSky {
+    /// Scale from kcd/m² to the renderer's radiance, so that a midday sky is around 1.
+    const DEFAULT_LUMINANCE_SCALE: f64 = 0.1;
+    /// Luminance of the sun outside the atmosphere, in kcd/m².
+    const SUN_LUMINANCE: f64 = 1.6e6;
+    const SUN_ANGULAR_RADIUS: f64 = 0.265;
+
+    /// `sun_elevation` and `sun_azimuth` are in degrees.
+    pub fn new(sun_elevation: f64, sun_azimuth: f64, turbidity: f64) -> Self {
+        let (el, az) = (deg_to_rad(sun_elevation), deg_to_rad(sun_azimuth));
+        let sun_direction = Vec3::new(el.cos() * az.sin(), el.sin(), -el.cos() * az.cos());
+        let t = turbidity;
+
+        let perez_lum = [
+            0.1787 * t - 1.4630,
+            -0.3554 * t + 0.4275,
+            -0.0227 * t + 5.3251,
+            0.1206 * t - 2.5771,
+            -0.0670 * t + 0.3703,
+        ];
+        let perez_x = [
+            -0.0193 * t - 0.2592,
+            -0.0665 * t + 0.0008,
+            -0.0004 * t + 0.2125,
+            -0.0641 * t - 0.8989,
+            -0.0033 * t + 0.0452,
+        ];
+        let perez_y = [
+            -0.0167 * t - 0.2608,
+            -0.0950 * t + 0.0092,
+            -0.0079 * t + 0.2102,
+            -0.0441 * t - 1.6537,
+            -0.0109 * t + 0.0529,
+        ];
+
+        // Zenith values, from the sun zenith angle.
+        let theta_s = PI / 2. - el.max(0.);
+        let chi = (4. / 9. - t / 120.) * (PI - 2. * theta_s);
+        let zenith_lum = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.);
+
+        let thetas = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.];
+        let poly = |c: [[f64; 4]; 3]| -> f64 {
+            let row = |r: [f64; 4]| r.iter().zip(thetas).map(|(a, b)| a * b).sum::<f64>();
+            t * t * row(c[0]) + t * row(c[1]) + row(c[2])
+        };
+        let zenith_x = poly([
+            [0.00166, -0.00375, 0.00209, 0.],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = poly([
+            [0.00275, -0.00610, 0.00317, 0.],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+
+        Self {
+            sun_direction,
+            turbidity,
+            perez_lum,
+            perez_x,
+            perez_y,
+            zenith: (zenith_lum, zenith_x, zenith_y),
+            luminance_scale: Self::DEFAULT_LUMINANCE_SCALE,
+        }
+    }
+
+    pub fn with_luminance_scale(mut self, luminance_scale: f64) -> Self {
+        self.luminance_scale = luminance_scale;
+        self
+    }
+
+    pub fn sun_direction(&self) -> Vec3 {
+        self.sun_direction
+    }
+
+    /// The sun disk matching this sky, to be pushed in the scene lights so it gets sampled explicitly.
+    pub fn sun_light(&self) -> SunLight {
+        SunLight::new(self.sun_direction, self.sun_radiance(), Self::SUN_ANGULAR_RADIUS)
+    }
+
+    fn sun_radiance(&self) -> Color {
+        // Extinction through the atmosphere, with Rayleigh scattering and Ångström's aerosol
+        // turbidity, for representative red, green and blue wavelengths (in µm).
+        let elevation = self.sun_direction.y().clamp(-1., 1.).asin();
+        if elevation <= 0. {
+            return Color::ZERO;
+        }
+        let zenith_deg = 90. - elevation.to_degrees();
+        let air_mass = (zenith_deg.to_radians().cos() + 0.15 * (93.885 - zenith_deg).powf(-1.253)).recip();
+        let beta = 0.04608 * self.turbidity - 0.04586;
+
+        let transmittance = |lambda: f64| -> f64 {
+            let tau_rayleigh = 0.008735 * lambda.powf(-4.08);
+            let tau_aerosol = beta * lambda.powf(-1.3);
+            f64::exp(-air_mass * (tau_rayleigh + tau_aerosol))
+        };
+
+        Self::SUN_LUMINANCE * self.luminance_scale
+            * Color::new(transmittance(0.65), transmittance(0.57), transmittance(0.475))
+    }
+
+    fn perez(c: &[f64; 5], cos_theta: f64, gamma: f64, cos_gamma: f64) -> f64 {
+        (1. + c[0] * f64::exp(c[1] / cos_theta)) * (1. + c[2] * f64::exp(c[3] * gamma) + c[4] * cos_gamma * cos_gamma)
+    }
+}
+
+impl Environment for PreethamSky {
+    fn radiance(&self, direction: Vec3) -> Color {
+        // Below the horizon, the horizon values are reused.
+        let direction = normalize(direction);
+        let cos_theta = direction.y().max(1e-3);
+        let cos_gamma = dot(direction, self.sun_direction).clamp(-1., 1.);
+        let gamma = cos_gamma.acos();
+
+        let cos_theta_s = self.sun_direction.y().max(1e-3);
+        let theta_s = cos_theta_s.acos();
+
+        let relative = |c: &[f64; 5]| Self::perez(c, cos_theta, gamma, cos_gamma) / Self::perez(c, 1., theta_s, cos_theta_s);
+        let (zenith_lum, zenith_x, zenith_y) = self.zenith;
+        let lum = zenith_lum * relative(&self.perez_lum) * self.luminance_scale;
+        let x = zenith_x * relative(&self.perez_x);
+        let y = zenith_y * relative(&self.perez_y);
+
+        // xyY to XYZ, then to linear sRGB.
+        let big_x = x / y * lum;
+        let big_z = (1. - x - y) / y * lum;
+        let r = 3.2406 * big_x - 1.5372 * lum - 0.4986 * big_z;
+        let g = -0.9689 * big_x + 1.8758 * lum + 0.0415 * big_z;
+        let b = 0.0557 * big_x - 0.2040 * lum + 1.0570 * big_z;
+
+        Color::new(r.max(0.), g.max(0.), b.max(0.))
+    }
+}
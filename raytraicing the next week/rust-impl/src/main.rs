@@ -1,59 +1,142 @@
-use std::{fs::File, rc::Rc};
+use std::{env, fs::File, path::Path, process, rc::Rc};
 
-use rand::random;
-
-use crate::{color::Color, geometry::Sphere, light::{hittable_list::HittableList, material::{DielectricMat, LambertianMat, Material, MetalMat}}, math::Point3, render::{pixel_buff::PixelBuff, Camera}};
+use crate::{color::Color, geometry::Sphere, light::{hittable_list::HittableList, material::{DielectricMat, LambertianMat, Material, MetalMat}}, math::{IndependentSampler, Point3, Sampler}, render::{denoise::Denoiser, hdr_image::HdrImage, pixel_buff::PixelBuff, progress::TerminalProgress, stats::RenderStats, tone_map::ToneMapping, Camera}, scene::Scene};
 
 pub mod color;
 pub mod light;
 pub mod math;
 pub mod render;
 pub mod geometry;
-
+pub mod scene;
+
+
+const DENOISE_USAGE: &str = "usage: denoise <color.pfm> <output.pfm|.png|.bmp> [--albedo <albedo.pfm>] [--normal <normal.pfm>] [--iterations <n>] [--tone-map <clamp|reinhard|aces|agx|hable>] [--exposure <stops>]\nimages are read from PFM files only, EXR isn't supported";
+
+/// Denoises a saved PFM image, guided by its albedo and normal AOVs when given.
+fn denoise_tool(args: &[String]) -> Result<(), String> {
+    let mut paths = vec![];
+    let (mut albedo, mut normal) = (None, None);
+    let mut denoiser = Denoiser::default();
+    let mut tone_mapping = ToneMapping::default();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value after {arg}"));
+        match arg.as_str() {
+            "--albedo" => albedo = Some(value()?),
+            "--normal" => normal = Some(value()?),
+            "--iterations" => {
+                denoiser.iterations = value()?.parse().ok()
+                    .filter(|&iterations| iterations <= Denoiser::MAX_ITERATIONS)
+                    .ok_or(format!("the iteration count must be at most {}", Denoiser::MAX_ITERATIONS))?;
+            }
+            "--tone-map" => tone_mapping.operator = value()?.parse()?,
+            "--exposure" => tone_mapping.exposure = value()?.parse().map_err(|_| "invalid exposure".to_string())?,
+            _ => paths.push(arg),
+        }
+    }
+    let [input, output] = paths[..] else {
+        return Err(DENOISE_USAGE.to_string());
+    };
+
+    let load = |path: &String| HdrImage::load_pfm(path).map_err(|e| format!("can't read {path}: {e}"));
+    let image = load(input)?;
+    let albedo = albedo.map(load).transpose()?;
+    let normal = normal.map(load).transpose()?;
+    let denoised = denoiser.denoise(&image, albedo.as_ref(), normal.as_ref());
+
+    let extension = Path::new(output).extension().and_then(|ext| ext.to_str());
+    if !matches!(extension, Some("pfm" | "png" | "bmp")) {
+        return Err(format!("unsupported output format for {output}, expected .pfm, .png or .bmp"));
+    }
+    let mut file = File::create(output).map_err(|e| format!("can't create {output}: {e}"))?;
+    let written = match extension {
+        Some("pfm") => denoised.write_into_pfm(&mut file),
+        Some("png") => {
+            let mut pixel_buff = PixelBuff::empty();
+            denoised.develop(&tone_mapping, &mut pixel_buff);
+            pixel_buff.write_into_png(&mut file)
+        }
+        _ => {
+            let mut pixel_buff = PixelBuff::empty();
+            denoised.develop(&tone_mapping, &mut pixel_buff);
+            pixel_buff.write_into_bmp(&mut file);
+            Ok(())
+        }
+    };
+    written.map_err(|e| format!("can't write {output}: {e}"))
+}
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "denoise") {
+        if let Err(e) = denoise_tool(&args[1..]) {
+            eprintln!("{e}");
+            process::exit(1);
+        }
+        return;
+    }
+
+    // Statistics are printed and saved as JSON to the path given after --stats.
+    let stats_path = match args.iter().position(|arg| arg == "--stats") {
+        Some(i) => match args.get(i + 1) {
+            Some(path) => Some(path.clone()),
+            None => {
+                eprintln!("missing path after --stats");
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
+
     println!("Starting [App]");
 
     let mut pixel_buff = PixelBuff::empty();
 
-    let mut world = HittableList::empty();
-
-    let ground_mat = Rc::new(LambertianMat::new(Color::new(0.5,0.5,0.5)));
-    world.push(Rc::new(Sphere::new(Point3::new(0., -1000., 0.), 1000., ground_mat)));
-
-
-    for a in -11..11{
-        for b in -11..11{
-            let (a,b) = (a as f64, b as f64);
-            let choose_mat = random::<f64>()%1.;
-            let center = Point3::new(a + 0.9*(random::<f64>()%1.), 0.2, b + 0.9*(random::<f64>()%1.));
-
-            if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
-                let spehre_mat:Rc<dyn Material> = if choose_mat < 0.8{
-                    //Diffuse
-                    let albedo = Color::random() * Color::random();
-                    Rc::new(LambertianMat::new(albedo))
-                }else if choose_mat < 0.95{
-                    let albedo = Color::random_range(0.5, 1.);
-                    let fuzz = random::<f64>()%0.5;
-                    Rc::new(MetalMat::new(albedo, fuzz))
-                }else{
-                    Rc::new(DielectricMat::new(1.5))
-                };
+    let mut stats = RenderStats::start();
+    let seed = 0;
+    let world = stats.time_phase("scene build", || {
+        let mut world = HittableList::empty();
+        let mut sampler = IndependentSampler::new(seed);
+
+        let ground_mat = Rc::new(LambertianMat::new(Color::new(0.5,0.5,0.5)));
+        world.push(Rc::new(Sphere::new(Point3::new(0., -1000., 0.), 1000., ground_mat)));
+
+
+        for a in -11..11{
+            for b in -11..11{
+                let (a,b) = (a as f64, b as f64);
+                let choose_mat = sampler.get_1d();
+                let center = Point3::new(a + 0.9*sampler.get_1d(), 0.2, b + 0.9*sampler.get_1d());
+
+                if (center - Point3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+                    let spehre_mat:Rc<dyn Material> = if choose_mat < 0.8{
+                        //Diffuse
+                        let albedo = Color::random(&mut sampler) * Color::random(&mut sampler);
+                        Rc::new(LambertianMat::new(albedo))
+                    }else if choose_mat < 0.95{
+                        let albedo = Color::random_range(&mut sampler, 0.5, 1.);
+                        let fuzz = sampler.get_1d()%0.5;
+                        Rc::new(MetalMat::new(albedo, fuzz))
+                    }else{
+                        Rc::new(DielectricMat::new(1.5))
+                    };
             
-            world.push(Rc::new(Sphere::new(center, 0.2, spehre_mat)));
+                world.push(Rc::new(Sphere::new(center, 0.2, spehre_mat)));
+                }
             }
         }
-    }
 
-    let mat1 = Rc::new(DielectricMat::new(1.5));
-    world.push(Rc::new(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, mat1)));
+        let mat1 = Rc::new(DielectricMat::new(1.5));
+        world.push(Rc::new(Sphere::new(Point3::new(0.0, 1.0, 0.0), 1.0, mat1)));
 
-    let mat2 = Rc::new(LambertianMat::new(Color::new(0.4, 0.2, 0.1)));
-    world.push(Rc::new(Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, mat2)));
+        let mat2 = Rc::new(LambertianMat::new(Color::new(0.4, 0.2, 0.1)));
+        world.push(Rc::new(Sphere::new(Point3::new(-4.0, 1.0, 0.0), 1.0, mat2)));
 
-    let mat3 = Rc::new(MetalMat::new(Color::new(0.7, 0.6, 0.5), 0.0));
-    world.push(Rc::new(Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, mat3)));
+        let mat3 = Rc::new(MetalMat::new(Color::new(0.7, 0.6, 0.5), 0.0));
+        world.push(Rc::new(Sphere::new(Point3::new(4.0, 1.0, 0.0), 1.0, mat3)));
+        world
+    });
 
     let mut cam = Camera::default();
 
@@ -62,6 +145,7 @@ fn main() {
     cam.image_width       = 1080;
     cam.samples_per_pixel = 20;
     cam.max_depth         = 5;
+    cam.progress          = Rc::new(TerminalProgress::new());
 
     cam.vfov     = 20.;
     cam.lookfrom = Point3::new(13.,2.,3.);
@@ -71,19 +155,35 @@ fn main() {
     cam.defocus_angle = 0.6;
     cam.focus_dist    = 10.0;
 
-    cam.render(&world,&mut pixel_buff);
+    if args.iter().any(|arg| arg == "--denoise") {
+        cam.denoiser = Some(Denoiser::default());
+    }
+
+    let mut scene = Scene::new(world);
+    scene.seed = seed;
+    if let Err(e) = stats.time_phase("render", || cam.render(&scene,&mut pixel_buff)) {
+        eprintln!("{e}");
+    }
 
-    let path = "./img.bmp";
+    stats.time_phase("output", || {
+        let path = "./img.bmp";
 
-    let mut img = match File::create_new(path) {
-        Ok(f) => f,
-        Err(_) => File::options().write(true).truncate(true).open(path).unwrap(),
-    }; 
+        let mut img = match File::create_new(path) {
+            Ok(f) => f,
+            Err(_) => File::options().write(true).truncate(true).open(path).unwrap(),
+        }; 
 
-    // println!("{:?}",pixel_buff);
+        // println!("{:?}",pixel_buff);
 
-    pixel_buff.write_into_bmp(&mut img);
+        pixel_buff.write_into_bmp(&mut img);
+    });
 
-    drop(img);
+    stats.finish();
+    if let Some(path) = stats_path {
+        println!("{stats}");
+        if let Err(e) = stats.save_json(&path) {
+            eprintln!("can't save the statistics to {path}: {e}");
+        }
+    }
     println!("Done")
 }
@@ -0,0 +1,89 @@
+use crate::{
+    light::ray::Ray,
+    math::{Intervall, Point3, Vec3},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub x: Intervall,
+    pub y: Intervall,
+    pub z: Intervall,
+}
+
+impl Aabb {
+    pub const EMPTY: Self = Self::new(Intervall::EMPTY, Intervall::EMPTY, Intervall::EMPTY);
+
+    pub const fn new(x: Intervall, y: Intervall, z: Intervall) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Treat the two points a and b as extrema for the bounding box.
+    pub fn from_points(a: Point3, b: Point3) -> Self {
+        Self {
+            x: Intervall::new(a.x().min(b.x()), a.x().max(b.x())),
+            y: Intervall::new(a.y().min(b.y()), a.y().max(b.y())),
+            z: Intervall::new(a.z().min(b.z()), a.z().max(b.z())),
+        }
+    }
+
+    /// The box tightly enclosing the two input boxes.
+    pub fn surrounding(box0: &Aabb, box1: &Aabb) -> Self {
+        Self {
+            x: Intervall::enclosing(box0.x, box1.x),
+            y: Intervall::enclosing(box0.y, box1.y),
+            z: Intervall::enclosing(box0.z, box1.z),
+        }
+    }
+
+    pub fn axis_intervall(&self, n: usize) -> Intervall {
+        match n {
+            0 => self.x,
+            1 => self.y,
+            _ => self.z,
+        }
+    }
+
+    pub fn hit(&self, ray: &Ray, mut ray_t: Intervall) -> bool {
+        for axis in 0..3 {
+            let ax = self.axis_intervall(axis);
+            let adinv = ray.direction[axis].recip();
+
+            let t0 = (ax.min - ray.origine[axis]) * adinv;
+            let t1 = (ax.max - ray.origine[axis]) * adinv;
+
+            ray_t.min = ray_t.min.max(t0.min(t1));
+            ray_t.max = ray_t.max.min(t0.max(t1));
+
+            if ray_t.max <= ray_t.min {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() { 0 } else { 2 }
+        } else if self.y.size() > self.z.size() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        Point3::new(
+            0.5 * (self.x.min + self.x.max),
+            0.5 * (self.y.min + self.y.max),
+            0.5 * (self.z.min + self.z.max),
+        )
+    }
+
+    pub fn diagonal(&self) -> Vec3 {
+        Vec3::new(self.x.size(), self.y.size(), self.z.size())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.x.size() < 0. || self.y.size() < 0. || self.z.size() < 0.
+    }
+}
@@ -0,0 +1,88 @@
+/// Piecewise constant 1D distribution over [0,1), used for importance sampling tabulated functions.
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f64>) -> Self {
+        let n = func.len();
+        let mut cdf = vec![0.; n + 1];
+        for i in 0..n {
+            cdf[i + 1] = cdf[i] + func[i].abs() / n as f64;
+        }
+
+        let integral = cdf[n];
+        if integral == 0. {
+            // Nothing to importance sample, fall back to uniform sampling.
+            cdf.iter_mut().enumerate().for_each(|(i, c)| *c = i as f64 / n as f64);
+        } else {
+            cdf.iter_mut().for_each(|c| *c /= integral);
+        }
+
+        Self { func, cdf, integral }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// Maps a uniform `u` in [0,1) to a sample in [0,1), returns it with its pdf and the index
+    /// of the segment it falls in.
+    pub fn sample_continuous(&self, u: f64) -> (f64, f64, usize) {
+        let offset = (self.cdf.partition_point(|&c| c <= u).max(1) - 1).min(self.count() - 1);
+
+        let mut du = u - self.cdf[offset];
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        if width > 0. {
+            du /= width;
+        }
+
+        let pdf = if self.integral > 0. { self.func[offset].abs() / self.integral } else { 1. };
+        ((offset as f64 + du) / self.count() as f64, pdf, offset)
+    }
+
+    pub fn pdf(&self, x: f64) -> f64 {
+        if self.integral <= 0. {
+            return 1.;
+        }
+        let offset = ((x * self.count() as f64) as usize).min(self.count() - 1);
+        self.func[offset].abs() / self.integral
+    }
+}
+
+/// Piecewise constant 2D distribution over [0,1)², `func` being given row by row.
+pub struct Distribution2D {
+    conditionals: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[f64], width: usize, height: usize) -> Self {
+        let conditionals: Vec<_> = func
+            .chunks_exact(width)
+            .take(height)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(conditionals.iter().map(Distribution1D::integral).collect());
+
+        Self { conditionals, marginal }
+    }
+
+    /// Returns a sample (u, v) in [0,1)² with its pdf.
+    pub fn sample_continuous(&self, u1: f64, u2: f64) -> ((f64, f64), f64) {
+        let (v, pdf_v, row) = self.marginal.sample_continuous(u2);
+        let (u, pdf_u, _) = self.conditionals[row].sample_continuous(u1);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let row = ((v * self.marginal.count() as f64) as usize).min(self.marginal.count() - 1);
+        self.marginal.pdf(v) * self.conditionals[row].pdf(u)
+    }
+}
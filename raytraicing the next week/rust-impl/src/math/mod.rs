@@ -1,7 +1,13 @@
+mod aabb;
+mod distribution;
+mod sampler;
 mod vec3;
 
 use std::ops::{Add, Mul};
 
+pub use aabb::*;
+pub use distribution::*;
+pub use sampler::*;
 pub use vec3::*;
 
 #[derive(Debug, Clone, Copy)]
@@ -11,10 +17,10 @@ pub struct Intervall {
 }
 
 impl Intervall {
-    // const EMPTY: Self = Self {
-    //     min: f64::INFINITY,
-    //     max: -f64::INFINITY,
-    // };
+    pub const EMPTY: Self = Self {
+        min: f64::INFINITY,
+        max: -f64::INFINITY,
+    };
     // const UNIVERS: Self = Self {
     //     min: -f64::INFINITY,
     //     max: f64::INFINITY,
@@ -24,6 +30,11 @@ impl Intervall {
         Self { min, max }
     }
 
+    /// The intervall tightly enclosing the two input intervalls.
+    pub fn enclosing(a: Self, b: Self) -> Self {
+        Self { min: a.min.min(b.min), max: a.max.max(b.max) }
+    }
+
     pub fn size(&self) -> f64 {
         self.max - self.min
     }
@@ -51,4 +62,11 @@ where
 
 pub fn deg_to_rad(deg:f64)->f64{
     deg * core::f64::consts::PI / 180.
-}
\ No newline at end of file
+}
+
+/// Power heuristic (beta = 2) weight for multiple importance sampling. A zero `other_pdf`
+/// means the other strategy can't produce the sample.
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0. { 0. } else { a / (a + b) }
+}
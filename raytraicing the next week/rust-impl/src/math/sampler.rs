@@ -0,0 +1,420 @@
+/// Source of every random number of a render. Each pixel sample draws its dimensions in
+/// order (pixel position, time, lens, then two per bounce, ...) from a stream that only
+/// depends on the seed, the pixel and the sample index, so a render is reproducible whatever
+/// the order, or the thread, its pixels are rendered in.
+pub trait Sampler {
+    /// Switches to the sample `sample_index` of the pixel `(i, j)`, from its first dimension.
+    fn start_pixel_sample(&mut self, pixel: (usize, usize), sample_index: usize);
+
+    /// Uniform number in [0, 1).
+    fn get_1d(&mut self) -> f64;
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+/// Which sampler a render uses.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SamplerKind {
+    /// Independent uniform numbers.
+    #[default]
+    Independent,
+    /// Jittered strata, shuffled independently for each dimension.
+    Stratified,
+    /// Owen scrambled Halton sequence.
+    Halton,
+    /// Owen scrambled Sobol (0, 2)-sequence, padded with shuffled copies for the other dimensions.
+    Sobol,
+    /// Owen scrambled Sobol sequence spread over the image along a Morton curve, which gives
+    /// its error the distribution of blue noise.
+    BlueNoise,
+}
+
+impl SamplerKind {
+    pub fn create(&self, seed: u64, samples_per_pixel: usize, resolution: (usize, usize)) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(seed, samples_per_pixel)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed)),
+            SamplerKind::BlueNoise => Box::new(BlueNoiseSampler::new(seed, samples_per_pixel, resolution)),
+        }
+    }
+}
+
+pub struct IndependentSampler {
+    seed: u64,
+    rng: SplitMix64,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, rng: SplitMix64::new(seed) }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, pixel: (usize, usize), sample_index: usize) {
+        self.rng = SplitMix64::new(mix(self.seed ^ mix(pixel_key(pixel) ^ sample_index as u64)));
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.next_f64()
+    }
+}
+
+pub struct StratifiedSampler {
+    seed: u64,
+    samples_per_pixel: usize,
+    strata: (usize, usize), // Along x and y, for the 2D samples
+    pixel: u64,
+    sample_index: usize,
+    dimension: u64,
+    rng: SplitMix64,
+}
+
+impl StratifiedSampler {
+    pub fn new(seed: u64, samples_per_pixel: usize) -> Self {
+        // Close to square strata, at least as many as samples.
+        let samples_per_pixel = samples_per_pixel.max(1);
+        let x = (samples_per_pixel as f64).sqrt() as usize;
+        let y = samples_per_pixel.div_ceil(x);
+        Self {
+            seed,
+            samples_per_pixel,
+            strata: (x, y),
+            pixel: 0,
+            sample_index: 0,
+            dimension: 0,
+            rng: SplitMix64::new(seed),
+        }
+    }
+
+    /// Stratum of the current sample, the strata being shuffled for each pixel and dimension.
+    fn stratum(&mut self, count: usize) -> usize {
+        let hash = mix(self.pixel ^ mix(self.dimension ^ self.seed)) as u32;
+        self.dimension += 1;
+        permutation_element((self.sample_index % count) as u32, count as u32, hash) as usize
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, pixel: (usize, usize), sample_index: usize) {
+        self.pixel = pixel_key(pixel);
+        self.sample_index = sample_index;
+        self.dimension = 0;
+        self.rng = SplitMix64::new(mix(self.seed ^ mix(self.pixel ^ sample_index as u64)));
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let stratum = self.stratum(self.samples_per_pixel);
+        (stratum as f64 + self.rng.next_f64()) / self.samples_per_pixel as f64
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (x, y) = self.strata;
+        let stratum = self.stratum(x * y);
+        let (jx, jy) = (self.rng.next_f64(), self.rng.next_f64());
+        (((stratum % x) as f64 + jx) / x as f64, ((stratum / x) as f64 + jy) / y as f64)
+    }
+}
+
+pub struct HaltonSampler {
+    seed: u64,
+    pixel: u64,
+    sample_index: u64,
+    dimension: usize,
+    rng: SplitMix64, // For the dimensions past the prime table
+}
+
+impl HaltonSampler {
+    const PRIMES: [u64; 64] = [
+        2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103,
+        107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199, 211, 223,
+        227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307, 311,
+    ];
+
+    pub fn new(seed: u64) -> Self {
+        Self { seed, pixel: 0, sample_index: 0, dimension: 0, rng: SplitMix64::new(seed) }
+    }
+
+    /// Radical inverse of `a` in `base`, each digit being permuted according to the digits
+    /// before it, which is Owen scrambling.
+    fn owen_scrambled_radical_inverse(base: u64, mut a: u64, hash: u64) -> f64 {
+        let inv_base = 1. / base as f64;
+        let mut inv_base_m = 1.;
+        let mut reversed_digits = 0_u64;
+        // Past the float precision, more digits change nothing.
+        while 1. - (base - 1) as f64 * inv_base_m < 1. {
+            let next = a / base;
+            let digit = (a - next * base) as u32;
+            let digit_hash = mix(hash ^ reversed_digits) as u32;
+            let digit = permutation_element(digit, base as u32, digit_hash) as u64;
+            reversed_digits = reversed_digits.wrapping_mul(base).wrapping_add(digit);
+            inv_base_m *= inv_base;
+            a = next;
+        }
+        (inv_base_m * reversed_digits as f64).min(ONE_MINUS_EPSILON)
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, pixel: (usize, usize), sample_index: usize) {
+        self.pixel = pixel_key(pixel);
+        self.sample_index = sample_index as u64;
+        self.dimension = 0;
+        self.rng = SplitMix64::new(mix(self.seed ^ mix(self.pixel ^ self.sample_index)));
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        match Self::PRIMES.get(dimension) {
+            Some(&base) => {
+                let hash = mix(self.pixel ^ mix(dimension as u64 ^ self.seed));
+                Self::owen_scrambled_radical_inverse(base, self.sample_index, hash)
+            }
+            None => self.rng.next_f64(),
+        }
+    }
+}
+
+pub struct SobolSampler {
+    seed: u64,
+    pixel: u64,
+    sample_index: u32,
+    dimension: u64,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, pixel: 0, sample_index: 0, dimension: 0 }
+    }
+
+    /// Index of the current sample in the sequence used by the next dimensions, shuffled so
+    /// that each dimension pair gets its own ordering.
+    fn next_dimensions(&mut self, count: u64) -> (u32, u64) {
+        let hash = mix(self.pixel ^ mix(self.dimension ^ self.seed));
+        self.dimension += count;
+        (fast_owen_scramble(self.sample_index, hash as u32), hash)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, pixel: (usize, usize), sample_index: usize) {
+        self.pixel = pixel_key(pixel);
+        self.sample_index = sample_index as u32;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let (index, hash) = self.next_dimensions(1);
+        to_unit(fast_owen_scramble(sobol_0(index), (hash >> 32) as u32))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let (index, hash) = self.next_dimensions(2);
+        let hash_y = mix(hash);
+        (
+            to_unit(fast_owen_scramble(sobol_0(index), (hash >> 32) as u32)),
+            to_unit(fast_owen_scramble(sobol_1(index), (hash_y >> 32) as u32)),
+        )
+    }
+}
+
+/// Sobol sampler of Ahmed and Wonka, "Screen-Space Blue-Noise Diffusion of Monte Carlo
+/// Sampling Error via Hierarchical Ordering of Pixels" (2020): pixels take consecutive parts
+/// of a single sequence along a Morton curve, whose base 4 digits are randomly permuted.
+pub struct BlueNoiseSampler {
+    seed: u64,
+    log2_samples_per_pixel: u32,
+    base4_digits: u32,
+    morton_index: u64,
+    dimension: u64,
+}
+
+impl BlueNoiseSampler {
+    const PERMUTATIONS: [[u8; 4]; 24] = [
+        [0, 1, 2, 3], [0, 1, 3, 2], [0, 2, 1, 3], [0, 2, 3, 1], [0, 3, 2, 1], [0, 3, 1, 2],
+        [1, 0, 2, 3], [1, 0, 3, 2], [1, 2, 0, 3], [1, 2, 3, 0], [1, 3, 2, 0], [1, 3, 0, 2],
+        [2, 1, 0, 3], [2, 1, 3, 0], [2, 0, 1, 3], [2, 0, 3, 1], [2, 3, 0, 1], [2, 3, 1, 0],
+        [3, 1, 2, 0], [3, 1, 0, 2], [3, 2, 1, 0], [3, 2, 0, 1], [3, 0, 2, 1], [3, 0, 1, 2],
+    ];
+
+    pub fn new(seed: u64, samples_per_pixel: usize, (width, height): (usize, usize)) -> Self {
+        let log2_samples_per_pixel = samples_per_pixel.max(1).next_power_of_two().trailing_zeros();
+        let resolution = width.max(height).max(1).next_power_of_two();
+        let base4_digits = resolution.trailing_zeros() + log2_samples_per_pixel.div_ceil(2);
+        Self { seed, log2_samples_per_pixel, base4_digits, morton_index: 0, dimension: 0 }
+    }
+
+    /// Index of the current sample in the sequence for the current dimension.
+    fn sample_index(&self) -> u64 {
+        let odd = self.log2_samples_per_pixel & 1 == 1;
+        let last_digit = odd as u32;
+        let mut index = 0;
+        for i in (last_digit..self.base4_digits).rev() {
+            let shift = 2 * i - odd as u32;
+            let digit = (self.morton_index >> shift) & 3;
+            let higher_digits = self.morton_index >> (shift + 2);
+            let p = (mix(higher_digits ^ 0x5555_5555_u64.wrapping_mul(self.dimension)) >> 24) % 24;
+            index |= (Self::PERMUTATIONS[p as usize][digit as usize] as u64) << shift;
+        }
+        if odd {
+            let digit = self.morton_index & 1;
+            index |= digit ^ (mix((self.morton_index >> 1) ^ 0x5555_5555_u64.wrapping_mul(self.dimension)) & 1);
+        }
+        index
+    }
+}
+
+impl Sampler for BlueNoiseSampler {
+    fn start_pixel_sample(&mut self, (i, j): (usize, usize), sample_index: usize) {
+        self.morton_index = (morton_2d(i as u32, j as u32) << self.log2_samples_per_pixel) | sample_index as u64;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let index = self.sample_index() as u32;
+        self.dimension += 1;
+        let hash = mix(self.dimension ^ self.seed);
+        to_unit(fast_owen_scramble(sobol_0(index), hash as u32))
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let index = self.sample_index() as u32;
+        self.dimension += 2;
+        let hash = mix(self.dimension ^ self.seed);
+        (
+            to_unit(fast_owen_scramble(sobol_0(index), hash as u32)),
+            to_unit(fast_owen_scramble(sobol_1(index), (hash >> 32) as u32)),
+        )
+    }
+}
+
+const ONE_MINUS_EPSILON: f64 = 1. - f64::EPSILON / 2.;
+
+fn to_unit(bits: u32) -> f64 {
+    (bits as f64 / (1_u64 << 32) as f64).min(ONE_MINUS_EPSILON)
+}
+
+fn pixel_key((i, j): (usize, usize)) -> u64 {
+    mix((i as u64) << 32 | j as u64)
+}
+
+/// First dimension of the Sobol sequence, the van der Corput sequence.
+fn sobol_0(index: u32) -> u32 {
+    index.reverse_bits()
+}
+
+/// Second dimension of the Sobol sequence.
+fn sobol_1(mut index: u32) -> u32 {
+    let (mut value, mut v) = (0, 1_u32 << 31);
+    while index != 0 {
+        if index & 1 == 1 {
+            value ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    value
+}
+
+/// Owen scrambling of the bits of `v`, most significant first, with the hash based
+/// permutation of Laine and Karras.
+fn fast_owen_scramble(v: u32, seed: u32) -> u32 {
+    let mut v = v.reverse_bits();
+    v ^= v.wrapping_mul(0x3d20_adea);
+    v = v.wrapping_add(seed);
+    v = v.wrapping_mul((seed >> 16) | 1);
+    v ^= v.wrapping_mul(0x0552_6c56);
+    v ^= v.wrapping_mul(0x53a2_2864);
+    v.reverse_bits()
+}
+
+/// Element `i` of a random permutation of [0, l) picked by `p`, after Kensler,
+/// "Correlated Multi-Jittered Sampling" (2013).
+fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l.wrapping_sub(1);
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            return (i.wrapping_add(p)) % l;
+        }
+    }
+}
+
+/// Interleaves the bits of `x` and `y`.
+fn morton_2d(x: u32, y: u32) -> u64 {
+    let spread = |v: u32| {
+        let mut v = v as u64;
+        v = (v | (v << 16)) & 0x0000_ffff_0000_ffff;
+        v = (v | (v << 8)) & 0x00ff_00ff_00ff_00ff;
+        v = (v | (v << 4)) & 0x0f0f_0f0f_0f0f_0f0f;
+        v = (v | (v << 2)) & 0x3333_3333_3333_3333;
+        (v | (v << 1)) & 0x5555_5555_5555_5555
+    };
+    spread(x) | spread(y) << 1
+}
+
+/// SplitMix64 generator (Steele et al. 2014), whose output is fully specified so that a
+/// seed gives the same render whatever the version of the dependencies.
+struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        // `mix` steps the state by the golden ratio increment before finalizing it.
+        let z = mix(self.state);
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z
+    }
+
+    /// Uniform number in [0, 1), from the 53 high bits.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+}
+
+/// SplitMix64 finalizer, spreading every input bit over the output.
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Deterministic uniform number in [0, 1) hashed from `values`, for decisions that must not
+/// consume samples, such as stochastic alpha testing inside intersection routines.
+pub fn hash_to_unit(values: &[f64]) -> f64 {
+    let h = values.iter().fold(0_u64, |h, v| mix(h ^ v.to_bits()));
+    (h >> 11) as f64 / (1_u64 << 53) as f64
+}
@@ -3,7 +3,7 @@ use std::{
     ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, Neg, Sub},
 };
 
-use crate::math::lerp;
+use crate::math::Sampler;
 
 #[derive(Debug, Clone, Copy,PartialEq, PartialOrd)]
 pub struct Vec3([f64; 3]);
@@ -42,14 +42,12 @@ impl Vec3 {
         self.0[0].abs() < epsilon && self.0[1].abs() < epsilon && self.0[2].abs() < epsilon
     }
 
-    pub fn random() -> Self {
-        let a: [f64; 3] = rand::random();
-
-        Self(a.map(|f| f % 1.))
+    pub fn random(sampler: &mut dyn Sampler) -> Self {
+        Self([sampler.get_1d(), sampler.get_1d(), sampler.get_1d()])
     }
 
-    pub fn random_range(min: f64, max: f64) -> Self {
-        let a: [f64; 3] = rand::random::<[f64; 3]>().map(|f| min + (max - min) * (f));
+    pub fn random_range(sampler: &mut dyn Sampler, min: f64, max: f64) -> Self {
+        let a: [f64; 3] = [sampler.get_1d(), sampler.get_1d(), sampler.get_1d()].map(|f| min + (max - min) * (f));
 
         Self(a)
     }
@@ -162,31 +160,51 @@ pub fn normalize(u: Vec3) -> Vec3 {
     u / u.length()
 }
 
-pub fn random_unit_vec() -> Vec3 {
-    //! Fixme
-    const EPSILON: f64 = 1e-160;
-    loop {
-        let p = Vec3::random_range(-1., 1.);
-        let lensq = p.length_sq();
-        if EPSILON < lensq && lensq <= 1. {
-            return p;
-        }
-    }
+/// Uniformly distributed direction on the unit sphere, mapped from a single 2D sample so
+/// low discrepancy samplers keep their dimensions aligned (rejection would not).
+pub fn random_unit_vec(sampler: &mut dyn Sampler) -> Vec3 {
+    let (u1, u2) = sampler.get_2d();
+    let z = 1. - 2. * u1;
+    let r = f64::sqrt((1. - z * z).max(0.));
+    let phi = 2. * core::f64::consts::PI * u2;
+    Vec3([r * phi.cos(), r * phi.sin(), z])
 }
 
-pub fn random_in_unit_disk() -> Vec3 {
-    //! Fixme
-    loop {
-        let x = lerp(-1., 1., rand::random::<f64>() % 1.);
-        let y = lerp(-1., 1., rand::random::<f64>() % 1.);
-        if x * x + y * y < 1. {
-            return Vec3([x, y, 0.]);
-        }
+/// Uniformly distributed point in the unit disk, with Shirley and Chiu's concentric mapping.
+pub fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Vec3 {
+    let (u1, u2) = sampler.get_2d();
+    let (a, b) = (2. * u1 - 1., 2. * u2 - 1.);
+    if a == 0. && b == 0. {
+        return Vec3([0., 0., 0.]);
     }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, core::f64::consts::FRAC_PI_4 * (b / a))
+    } else {
+        (b, core::f64::consts::FRAC_PI_2 - core::f64::consts::FRAC_PI_4 * (a / b))
+    };
+    Vec3([r * theta.cos(), r * theta.sin(), 0.])
+}
+
+/// Returns a uniformly distributed direction in the cone of half angle `acos(cos_max)` around `axis`.
+pub fn random_in_cone(sampler: &mut dyn Sampler, axis: Vec3, cos_max: f64) -> Vec3 {
+    let (u1, u2) = sampler.get_2d();
+    let cos_theta = 1. - u1 * (1. - cos_max);
+    let sin_theta = f64::sqrt((1. - cos_theta * cos_theta).max(0.));
+    let phi = 2. * core::f64::consts::PI * u2;
+
+    let (t, b) = orthonormal_basis(axis);
+    sin_theta * phi.cos() * t + sin_theta * phi.sin() * b + cos_theta * axis
+}
+
+/// Builds two unit vectors that form an orthonormal basis with the unit vector `n`.
+pub fn orthonormal_basis(n: Vec3) -> (Vec3, Vec3) {
+    let a = if n.x().abs() > 0.9 { Vec3([0., 1., 0.]) } else { Vec3([1., 0., 0.]) };
+    let t = normalize(cross(a, n));
+    (t, cross(n, t))
 }
 
-pub fn random_on_hemisphere(normal: Vec3) -> Vec3 {
-    let p = random_unit_vec();
+pub fn random_on_hemisphere(sampler: &mut dyn Sampler, normal: Vec3) -> Vec3 {
+    let p = random_unit_vec(sampler);
     if dot(p, normal) > 0. { p } else { -p }
 }
 
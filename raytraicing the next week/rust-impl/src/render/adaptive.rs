@@ -0,0 +1,60 @@
+use crate::color::Color;
+
+/// Stops sampling a pixel once its estimate is precise enough, between `min_samples` and
+/// `max_samples` samples. The precision is the standard error of the pixel luminance,
+/// relative to that luminance.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveSampling {
+    pub noise_threshold: f64,
+    pub min_samples: usize,
+    pub max_samples: usize,
+}
+
+impl AdaptiveSampling {
+    /// Convergence is checked every this many samples, so a few lucky samples can't stop a pixel.
+    const BATCH: usize = 8;
+
+    pub fn new(noise_threshold: f64, min_samples: usize, max_samples: usize) -> Self {
+        let min_samples = min_samples.max(2);
+        Self { noise_threshold, min_samples, max_samples: max_samples.max(min_samples) }
+    }
+
+    pub(super) fn is_converged(&self, estimate: &PixelEstimate) -> bool {
+        if estimate.count < self.min_samples || !estimate.count.is_multiple_of(Self::BATCH) {
+            return false;
+        }
+        // Dark pixels are judged on an absolute scale, or they would never converge.
+        let std_error = f64::sqrt(estimate.variance() / estimate.count as f64);
+        std_error <= self.noise_threshold * estimate.mean_luminance.max(0.01)
+    }
+}
+
+/// Running sum of the samples of a pixel, with the mean and variance of their luminance
+/// (Welford's algorithm).
+#[derive(Debug, Clone, Copy)]
+pub(super) struct PixelEstimate {
+    pub sum: Color,
+    pub count: usize,
+    pub mean_luminance: f64,
+    pub m2: f64,
+}
+
+impl PixelEstimate {
+    pub fn new() -> Self {
+        Self { sum: Color::ZERO, count: 0, mean_luminance: 0., m2: 0. }
+    }
+
+    pub fn add(&mut self, sample: Color) {
+        self.sum += sample;
+        self.count += 1;
+
+        let luminance = 0.2126 * sample.x() + 0.7152 * sample.y() + 0.0722 * sample.z();
+        let delta = luminance - self.mean_luminance;
+        self.mean_luminance += delta / self.count as f64;
+        self.m2 += delta * (luminance - self.mean_luminance);
+    }
+
+    fn variance(&self) -> f64 {
+        if self.count < 2 { 0. } else { self.m2 / (self.count - 1) as f64 }
+    }
+}
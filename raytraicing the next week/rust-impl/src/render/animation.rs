@@ -0,0 +1,154 @@
+use std::{
+    fmt::Display,
+    ops::{Add, Mul, RangeInclusive, Sub},
+};
+
+use crate::math::{lerp, Point3};
+
+/// Camera pose at a given time, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CameraKeyframe {
+    pub time: f64,
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vfov: f64,
+    pub focus_dist: f64,
+}
+
+impl CameraKeyframe {
+    pub fn new(time: f64, lookfrom: Point3, lookat: Point3, vfov: f64, focus_dist: f64) -> Self {
+        Self { time, lookfrom, lookat, vfov, focus_dist }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    Spline, // Catmull-Rom, going through every keyframe
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnimationError {
+    NoKeyframes,
+    InvalidFrameRate(f64),
+    InvalidKeyframeTime(f64),
+}
+
+impl Display for AnimationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AnimationError::NoKeyframes => write!(f, "a camera animation needs at least one keyframe"),
+            AnimationError::InvalidFrameRate(rate) => write!(f, "invalid frame rate {rate}, it must be positive and finite"),
+            AnimationError::InvalidKeyframeTime(time) => write!(f, "invalid keyframe time {time}"),
+        }
+    }
+}
+
+impl std::error::Error for AnimationError {}
+
+/// Keyframed camera path, rendered as numbered frames by `Camera::render_sequence`.
+#[derive(Debug, Clone)]
+pub struct CameraAnimation {
+    keyframes: Vec<CameraKeyframe>,
+    interpolation: Interpolation,
+    frame_rate: f64,
+    frames: RangeInclusive<usize>,
+    shutter_angle: f64, // In degrees, 360 keeping the shutter open for the whole frame
+}
+
+impl CameraAnimation {
+    /// Frames go from time 0 to the last keyframe, the shutter being open for half of each frame.
+    pub fn new(mut keyframes: Vec<CameraKeyframe>, interpolation: Interpolation, frame_rate: f64) -> Result<Self, AnimationError> {
+        if keyframes.is_empty() {
+            return Err(AnimationError::NoKeyframes);
+        }
+        if !(frame_rate.is_finite() && frame_rate > 0.) {
+            return Err(AnimationError::InvalidFrameRate(frame_rate));
+        }
+        if let Some(key) = keyframes.iter().find(|key| !key.time.is_finite()) {
+            return Err(AnimationError::InvalidKeyframeTime(key.time));
+        }
+        keyframes.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        let last_frame = (keyframes.last().unwrap().time * frame_rate).round().max(0.) as usize;
+        Ok(Self { keyframes, interpolation, frame_rate, frames: 0..=last_frame, shutter_angle: 180. })
+    }
+
+    pub fn with_frames(mut self, frames: RangeInclusive<usize>) -> Self {
+        self.frames = frames;
+        self
+    }
+
+    /// 0 disables the motion blur.
+    pub fn with_shutter_angle(mut self, shutter_angle: f64) -> Self {
+        self.shutter_angle = shutter_angle.clamp(0., 360.);
+        self
+    }
+
+    pub fn frames(&self) -> RangeInclusive<usize> {
+        self.frames.clone()
+    }
+
+    pub fn frame_time(&self, frame: usize) -> f64 {
+        frame as f64 / self.frame_rate
+    }
+
+    /// Time the shutter stays open, from the start of each frame.
+    pub fn shutter_duration(&self) -> f64 {
+        self.shutter_angle / 360. / self.frame_rate
+    }
+
+    /// Camera pose at `time`, held still before the first and after the last keyframe.
+    pub fn pose_at(&self, time: f64) -> CameraKeyframe {
+        let keys = &self.keyframes;
+        let next = keys.partition_point(|k| k.time <= time);
+        if next == 0 {
+            return CameraKeyframe { time, ..keys[0] };
+        }
+        if next == keys.len() {
+            return CameraKeyframe { time, ..keys[next - 1] };
+        }
+
+        let (k1, k2) = (keys[next - 1], keys[next]);
+        let t = (time - k1.time) / (k2.time - k1.time);
+        match self.interpolation {
+            Interpolation::Linear => CameraKeyframe {
+                time,
+                lookfrom: lerp(k1.lookfrom, k2.lookfrom, t),
+                lookat: lerp(k1.lookat, k2.lookat, t),
+                vfov: lerp(k1.vfov, k2.vfov, t),
+                focus_dist: lerp(k1.focus_dist, k2.focus_dist, t),
+            },
+            Interpolation::Spline => {
+                // The end keyframes stand for their missing neighbours.
+                let k0 = keys[next.saturating_sub(2)];
+                let k3 = keys[(next + 1).min(keys.len() - 1)];
+                let times = [k0.time, k1.time, k2.time, k3.time];
+                CameraKeyframe {
+                    time,
+                    lookfrom: catmull_rom([k0.lookfrom, k1.lookfrom, k2.lookfrom, k3.lookfrom], times, t),
+                    lookat: catmull_rom([k0.lookat, k1.lookat, k2.lookat, k3.lookat], times, t),
+                    vfov: catmull_rom([k0.vfov, k1.vfov, k2.vfov, k3.vfov], times, t),
+                    focus_dist: catmull_rom([k0.focus_dist, k1.focus_dist, k2.focus_dist, k3.focus_dist], times, t),
+                }
+            }
+        }
+    }
+}
+
+/// Cubic Hermite curve between `p[1]` and `p[2]`, with Catmull-Rom tangents scaled to the
+/// keyframe spacing so uneven keyframes don't overshoot.
+fn catmull_rom<T>(p: [T; 4], times: [f64; 4], t: f64) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T>,
+{
+    let tangent = |a: usize, b: usize| {
+        let span = times[b] - times[a];
+        if span > 0. { (p[b] - p[a]) * ((times[2] - times[1]) / span) } else { p[2] - p[1] }
+    };
+    let (m1, m2) = (tangent(0, 2), tangent(1, 3));
+
+    let (t2, t3) = (t * t, t * t * t);
+    p[1] * (2. * t3 - 3. * t2 + 1.) + m1 * (t3 - 2. * t2 + t) + p[2] * (-2. * t3 + 3. * t2) + m2 * (t3 - t2)
+}
@@ -0,0 +1,237 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::{
+    color::{write_color_to_pixel_buff, Color, BLACK},
+    light::{ray::Ray, HitRecord},
+    math::{hash_to_unit, Point3, Vec3},
+    render::pixel_buff::PixelBuff,
+};
+
+/// Auxiliary output rendered alongside the image, from the first surface each camera
+/// sample hits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Aov {
+    Albedo,
+    Normal,       // Shading normal, facing the camera
+    Depth,        // Distance from the camera, 0 for the background
+    Position,     // World position, 0 for the background
+    ObjectId,     // 1 + index of the object in the world list, 0 for the background
+    MaterialId,   // 1 + rank of the material in order of first appearance, 0 for the background
+    Light(usize), // Direct lighting from a scene light at the first hit, exposed like the image
+}
+
+impl Aov {
+    /// IDs are taken from the first sample of a pixel instead of being averaged.
+    fn is_id(&self) -> bool {
+        matches!(self, Aov::ObjectId | Aov::MaterialId)
+    }
+}
+
+/// Which AOVs a camera renders, `lights` giving one buffer per scene light.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AovSet {
+    pub albedo: bool,
+    pub normal: bool,
+    pub depth: bool,
+    pub position: bool,
+    pub object_id: bool,
+    pub material_id: bool,
+    pub lights: bool,
+}
+
+impl AovSet {
+    pub fn all() -> Self {
+        Self { albedo: true, normal: true, depth: true, position: true, object_id: true, material_id: true, lights: true }
+    }
+
+    /// The AOVs to render for a scene with `light_count` lights.
+    pub fn list(&self, light_count: usize) -> Vec<Aov> {
+        let flags = [
+            (self.albedo, Aov::Albedo),
+            (self.normal, Aov::Normal),
+            (self.depth, Aov::Depth),
+            (self.position, Aov::Position),
+            (self.object_id, Aov::ObjectId),
+            (self.material_id, Aov::MaterialId),
+        ];
+        let lights = (0..light_count).filter(|_| self.lights).map(Aov::Light);
+        flags.into_iter().filter_map(|(on, aov)| on.then_some(aov)).chain(lights).collect()
+    }
+}
+
+/// What one camera sample contributes to the AOVs, filled by `ray_color` at the first hit.
+pub(super) struct AovSample {
+    pub albedo: Color,
+    pub normal: Vec3,
+    pub depth: f64,
+    pub position: Point3,
+    pub object_id: Option<usize>,
+    pub material: Option<*const ()>, // Identity of the material, never dereferenced
+    pub lights: Vec<Color>,          // Indexed like the scene lights
+}
+
+impl AovSample {
+    pub fn new(light_count: usize) -> Self {
+        Self {
+            albedo: BLACK,
+            normal: Vec3::ZERO,
+            depth: 0.,
+            position: Point3::ZERO,
+            object_id: None,
+            material: None,
+            lights: vec![BLACK; light_count],
+        }
+    }
+
+    /// Back to the background values, for the next sample.
+    pub fn reset(&mut self) {
+        (self.albedo, self.normal, self.depth, self.position) = (BLACK, Vec3::ZERO, 0., Point3::ZERO);
+        (self.object_id, self.material) = (None, None);
+        self.lights.fill(BLACK);
+    }
+
+    pub fn record_hit(&mut self, ray: &Ray, rec: &HitRecord) {
+        self.albedo = rec.mat.albedo(rec);
+        self.normal = rec.normal;
+        self.depth = rec.t * ray.direction.length();
+        self.position = rec.p;
+        self.object_id = rec.object_id;
+        self.material = Some(Rc::as_ptr(&rec.mat) as *const ());
+    }
+}
+
+/// Where `ray_color` adds what it finds to the AOVs: the sample of a camera ray, or for the
+/// ray scattered from the first hit, the light buffers of that sample, weighted by the bounce.
+pub(super) enum AovVertex<'a> {
+    Camera(&'a mut AovSample),
+    Bounce { lights: &'a mut [Color], weight: Color },
+}
+
+impl AovVertex<'_> {
+    pub fn add_light(&mut self, light: usize, radiance: Color) {
+        match self {
+            AovVertex::Camera(sample) => {
+                if let Some(color) = sample.lights.get_mut(light) {
+                    *color += radiance;
+                }
+            }
+            AovVertex::Bounce { lights, weight } => {
+                if let Some(color) = lights.get_mut(light) {
+                    *color += *weight * radiance;
+                }
+            }
+        }
+    }
+}
+
+/// AOV samples accumulated for every pixel of a render.
+pub(super) struct AovFilm {
+    aovs: Vec<Aov>,
+    width: usize,
+    height: usize,
+    sums: Vec<Vec<Color>>, // One buffer per AOV
+    counts: Vec<usize>,
+    material_ids: HashMap<*const (), usize>,
+}
+
+impl AovFilm {
+    pub fn new(aovs: Vec<Aov>, width: usize, height: usize) -> Self {
+        let sums = vec![vec![BLACK; width * height]; aovs.len()];
+        Self { aovs, width, height, sums, counts: vec![0; width * height], material_ids: HashMap::new() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.aovs.is_empty()
+    }
+
+    pub fn add(&mut self, pos: usize, sample: &AovSample) {
+        let next_id = self.material_ids.len() + 1;
+        let material_id = sample.material.map_or(0, |material| *self.material_ids.entry(material).or_insert(next_id));
+        let first = self.counts[pos] == 0;
+
+        for (aov, sums) in self.aovs.iter().zip(self.sums.iter_mut()) {
+            let value = match *aov {
+                Aov::Albedo => sample.albedo,
+                Aov::Normal => sample.normal,
+                Aov::Depth => Color::new(sample.depth, sample.depth, sample.depth),
+                Aov::Position => sample.position,
+                Aov::ObjectId => {
+                    let id = sample.object_id.map_or(0., |id| (id + 1) as f64);
+                    Color::new(id, id, id)
+                }
+                Aov::MaterialId => Color::new(material_id as f64, material_id as f64, material_id as f64),
+                Aov::Light(light) => sample.lights.get(light).copied().unwrap_or(BLACK),
+            };
+            if !aov.is_id() {
+                sums[pos] += value;
+            } else if first {
+                sums[pos] = value;
+            }
+        }
+        self.counts[pos] += 1;
+    }
+
+    /// Averaged values of `aov`, None when it wasn't rendered.
+    pub fn image(&self, aov: Aov, exposure_scale: f64) -> Option<AovImage> {
+        let k = self.aovs.iter().position(|&a| a == aov)?;
+        let scale = if let Aov::Light(_) = aov { exposure_scale } else { 1. };
+        let pixels = self.sums[k].iter().zip(self.counts.iter())
+            .map(|(&sum, &count)| if aov.is_id() { sum } else { sum * (scale / count.max(1) as f64) })
+            .collect();
+        Some(AovImage { aov, width: self.width, height: self.height, pixels })
+    }
+}
+
+/// Rendered AOV, with one linear value per pixel, IDs being stored in every channel.
+#[derive(Debug, Clone)]
+pub struct AovImage {
+    pub aov: Aov,
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl AovImage {
+    /// Maps the values to displayable colors, to be written like any image: normals from
+    /// [-1, 1], depths and positions stretched over their range, and one random color per ID.
+    pub fn to_pixel_buff(&self) -> PixelBuff {
+        let mut pixel_buff = PixelBuff::zeroed(self.height, self.width);
+        let to_bytes = |c: Color| {
+            let byte = |x: f64| (255. * x.clamp(0., 1.)).round() as u8;
+            [byte(c.x()), byte(c.y()), byte(c.z())]
+        };
+
+        match self.aov {
+            Aov::Albedo | Aov::Light(_) => {
+                for (pos, &color) in self.pixels.iter().enumerate() {
+                    write_color_to_pixel_buff(&mut pixel_buff.pixels, pos, color);
+                }
+            }
+            Aov::Normal => {
+                for (pos, &n) in self.pixels.iter().enumerate() {
+                    pixel_buff.pixels[pos] = to_bytes(0.5 * (n + Color::new(1., 1., 1.)));
+                }
+            }
+            Aov::Depth | Aov::Position => {
+                // The background is left black, out of the range.
+                let (mut min, mut max) = ([f64::INFINITY; 3], [f64::NEG_INFINITY; 3]);
+                for p in self.pixels.iter().filter(|p| !p.near_zero()) {
+                    for k in 0..3 {
+                        (min[k], max[k]) = (min[k].min(p[k]), max[k].max(p[k]));
+                    }
+                }
+                let stretch = |p: Color, k: usize| if max[k] > min[k] { (p[k] - min[k]) / (max[k] - min[k]) } else { 1. };
+                for (pos, &p) in self.pixels.iter().enumerate().filter(|(_, p)| !p.near_zero()) {
+                    pixel_buff.pixels[pos] = to_bytes(Color::new(stretch(p, 0), stretch(p, 1), stretch(p, 2)));
+                }
+            }
+            Aov::ObjectId | Aov::MaterialId => {
+                for (pos, &id) in self.pixels.iter().enumerate().filter(|(_, id)| id.x() > 0.) {
+                    let channel = |k: f64| 0.2 + 0.8 * hash_to_unit(&[id.x(), k]);
+                    pixel_buff.pixels[pos] = to_bytes(Color::new(channel(0.), channel(1.), channel(2.)));
+                }
+            }
+        }
+        pixel_buff
+    }
+}
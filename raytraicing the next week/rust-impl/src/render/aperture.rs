@@ -0,0 +1,77 @@
+use std::{f64::consts::PI, rc::Rc};
+
+use crate::math::{random_in_unit_disk, Distribution2D, Sampler, Vec3};
+
+/// Grayscale image of an aperture, for custom bokeh shapes (stars, hearts...).
+pub struct ApertureMask {
+    distribution: Distribution2D,
+}
+
+impl ApertureMask {
+    /// `weights` holds the transmission of each texel, row by row.
+    pub fn new(width: usize, height: usize, weights: &[f64]) -> Self {
+        assert_eq!(weights.len(), width * height, "an aperture mask needs width * height weights");
+        Self { distribution: Distribution2D::new(weights, width, height) }
+    }
+}
+
+/// Shape of the lens aperture, which gives its shape to out of focus highlights.
+#[derive(Clone, Default)]
+pub enum Aperture {
+    #[default]
+    Circular,
+    /// Regular polygon formed by `blades` blades, `rotation` being in degrees.
+    Polygonal { blades: usize, rotation: f64 },
+    /// Image based aperture, the image covering the square enclosing the defocus disk.
+    Image(Rc<ApertureMask>),
+}
+
+impl Aperture {
+    /// Returns a random point of the aperture, within the unit disk (z = 0).
+    pub fn sample(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        match self {
+            Aperture::Circular => random_in_unit_disk(sampler),
+            Aperture::Polygonal { blades, rotation } => {
+                // Uniform sampling of one of the equal triangles between the center and two
+                // consecutive vertices.
+                let blades = (*blades).max(3);
+                let step = 2. * PI / blades as f64;
+                let k = ((sampler.get_1d() * blades as f64) as usize).min(blades - 1);
+                let angle0 = rotation.to_radians() + k as f64 * step;
+                let (a, b) = (
+                    Vec3::new(angle0.cos(), angle0.sin(), 0.),
+                    Vec3::new((angle0 + step).cos(), (angle0 + step).sin(), 0.),
+                );
+
+                let (u1, v) = sampler.get_2d();
+                let su = u1.sqrt();
+                su * (1. - v) * a + su * v * b
+            }
+            Aperture::Image(mask) => {
+                let (u1, u2) = sampler.get_2d();
+                let ((u, v), _) = mask.distribution.sample_continuous(u1, u2);
+                Vec3::new(2. * u - 1., 1. - 2. * v, 0.)
+            }
+        }
+    }
+}
+
+/// Photographic exposure settings. The exposure scale is normalized so that the "sunny 16"
+/// settings (f/16, 1/100 s, ISO 100) leave the scene radiance unchanged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhysicalExposure {
+    pub f_stop: f64,
+    pub shutter_speed: f64, // In seconds
+    pub iso: f64,
+}
+
+impl PhysicalExposure {
+    pub fn new(f_stop: f64, shutter_speed: f64, iso: f64) -> Self {
+        Self { f_stop, shutter_speed, iso }
+    }
+
+    pub fn scale(&self) -> f64 {
+        const SUNNY_16: f64 = (1. / 100.) / (16. * 16.);
+        (self.iso / 100.) * self.shutter_speed / (self.f_stop * self.f_stop) / SUNNY_16
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{
+    color::Color,
+    math::dot,
+    render::hdr_image::HdrImage,
+};
+
+/// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010), guided by the albedo and
+/// normal AOVs. Each iteration blurs with a 5x5 kernel whose taps are twice as far apart as
+/// the previous one, neighbours being weighted down across color, albedo and normal edges.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Denoiser {
+    pub iterations: usize,
+    pub sigma_color: f64,  // On square rooted colors, halved at every iteration as the noise gets smoothed out
+    pub sigma_albedo: f64,
+    pub sigma_normal: f64,
+}
+
+impl Denoiser {
+    const KERNEL: [f64; 5] = [1. / 16., 1. / 4., 3. / 8., 1. / 4., 1. / 16.];
+    /// Iterations past this one would space the taps wider than any image, they are skipped.
+    pub const MAX_ITERATIONS: usize = 16;
+
+    pub fn new(iterations: usize, sigma_color: f64, sigma_albedo: f64, sigma_normal: f64) -> Self {
+        Self { iterations, sigma_color, sigma_albedo, sigma_normal }
+    }
+
+    /// Filters `image`, the guides having its size. Texture detail is kept by filtering the
+    /// image divided by the albedo, multiplied back afterward.
+    pub fn denoise(&self, image: &HdrImage, albedo: Option<&HdrImage>, normal: Option<&HdrImage>) -> HdrImage {
+        let (width, height) = (image.width, image.height);
+        let same_size = |guide: &HdrImage| (guide.width, guide.height) == (width, height);
+        let albedo = albedo.filter(|albedo| same_size(albedo)).map(|albedo| &albedo.pixels);
+        let normal = normal.filter(|normal| same_size(normal)).map(|normal| &normal.pixels);
+
+        // Black albedos (the background, lights) can't be divided by, they are filtered as is.
+        let demodulation: Vec<Color> = match albedo {
+            Some(albedo) => albedo.iter()
+                .map(|a| Color::new(Self::divisor(a.x()), Self::divisor(a.y()), Self::divisor(a.z())))
+                .collect(),
+            None => vec![Color::new(1., 1., 1.); width * height],
+        };
+        let mut current: Vec<Color> = image.pixels.iter().zip(demodulation.iter())
+            .map(|(&c, &d)| Color::new(c.x() / d.x(), c.y() / d.y(), c.z() / d.z()))
+            .collect();
+        let mut next = current.clone();
+
+        let inv_albedo_var = 1. / (self.sigma_albedo * self.sigma_albedo).max(1e-12);
+        let inv_normal_var = 1. / (self.sigma_normal * self.sigma_normal).max(1e-12);
+        for iteration in 0..self.iterations.min(Self::MAX_ITERATIONS) {
+            let step = 1_isize << iteration;
+            let sigma_color = self.sigma_color / f64::exp2(iteration as f64);
+            let inv_color_var = 1. / (sigma_color * sigma_color).max(1e-12);
+
+            for j in 0..height {
+                for i in 0..width {
+                    let p = j * width + i;
+                    let mut sum = Color::ZERO;
+                    let mut weight_sum = 0.;
+                    for (dy, ky) in Self::KERNEL.iter().enumerate() {
+                        for (dx, kx) in Self::KERNEL.iter().enumerate() {
+                            let qi = (i as isize + step * (dx as isize - 2)).clamp(0, width as isize - 1) as usize;
+                            let qj = (j as isize + step * (dy as isize - 2)).clamp(0, height as isize - 1) as usize;
+                            let q = qj * width + qi;
+
+                            let mut exponent = (Self::compress(current[p]) - Self::compress(current[q])).length_sq() * inv_color_var;
+                            if let Some(albedo) = albedo {
+                                exponent += (albedo[p] - albedo[q]).length_sq() * inv_albedo_var;
+                            }
+                            if let Some(normal) = normal {
+                                let cos = dot(normal[p], normal[q]).min(1.);
+                                exponent += (1. - cos) * inv_normal_var;
+                            }
+
+                            let weight = kx * ky * f64::exp(-exponent);
+                            sum += weight * current[q];
+                            weight_sum += weight;
+                        }
+                    }
+                    // The center tap always has a weight of kx * ky, never 0.
+                    next[p] = sum / weight_sum;
+                }
+            }
+            std::mem::swap(&mut current, &mut next);
+        }
+
+        let pixels = current.iter().zip(demodulation.iter()).map(|(&c, &d)| c * d).collect();
+        HdrImage::new(width, height, pixels)
+    }
+
+    /// Brings colors closer to how they are displayed, so that edges in dark areas count.
+    fn compress(c: Color) -> Color {
+        Color::new(c.x().max(0.).sqrt(), c.y().max(0.).sqrt(), c.z().max(0.).sqrt())
+    }
+
+    fn divisor(albedo: f64) -> f64 {
+        if albedo > 1e-3 { albedo } else { 1. }
+    }
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self::new(
+            5,    // iterations
+            0.2,  // sigma_color
+            0.1,  // sigma_albedo
+            0.3,  // sigma_normal
+        )
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    color::Color,
+    math::SamplerKind,
+    render::{adaptive::PixelEstimate, hdr_image::HdrImage},
+};
+
+/// Accumulated samples of every pixel of a render, which can be saved to a checkpoint file
+/// and loaded back to continue the render. The seed and sampler are kept with the samples,
+/// as the samples added on resume must come from the same streams.
+pub struct Film {
+    pub(super) width: usize,
+    pub(super) height: usize,
+    pub(super) seed: u64,
+    pub(super) sampler: SamplerKind,
+    pub(super) pixels: Vec<PixelEstimate>,
+}
+
+impl Film {
+    const MAGIC: &[u8; 8] = b"RTFILM02";
+    const HEADER_BYTES: u64 = 8 + 4 * 8;
+    const PIXEL_BYTES: u64 = 6 * 8;
+    const SAMPLERS: [SamplerKind; 5] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+        SamplerKind::BlueNoise,
+    ];
+
+    pub(super) fn new(width: usize, height: usize, seed: u64, sampler: SamplerKind) -> Self {
+        Self { width, height, seed, sampler, pixels: vec![PixelEstimate::new(); width * height] }
+    }
+
+    /// Writes the film next to `path` first, with `.tmp` appended to its name, then moves it
+    /// over, so an interrupted save doesn't destroy the previous checkpoint.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut tmp_name = path.file_name().map(OsString::from).unwrap_or_default();
+        tmp_name.push(".tmp");
+        let tmp_path = path.with_file_name(tmp_name);
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+
+        writer.write_all(Self::MAGIC)?;
+        writer.write_all(&(self.width as u64).to_le_bytes())?;
+        writer.write_all(&(self.height as u64).to_le_bytes())?;
+        writer.write_all(&self.seed.to_le_bytes())?;
+        let sampler = Self::SAMPLERS.iter().position(|&s| s == self.sampler).unwrap();
+        writer.write_all(&(sampler as u64).to_le_bytes())?;
+        for pixel in self.pixels.iter() {
+            let values = [pixel.sum.x(), pixel.sum.y(), pixel.sum.z(), pixel.mean_luminance, pixel.m2];
+            for value in values {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+            writer.write_all(&(pixel.count as u64).to_le_bytes())?;
+        }
+        writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+        fs::rename(tmp_path, path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0_u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != Self::MAGIC {
+            return Err(invalid("not a film checkpoint"));
+        }
+
+        let mut read_u64 = || -> io::Result<u64> {
+            let mut bytes = [0_u8; 8];
+            reader.read_exact(&mut bytes)?;
+            Ok(u64::from_le_bytes(bytes))
+        };
+        let (width, height) = (read_u64()?, read_u64()?);
+        let seed = read_u64()?;
+        let sampler = *usize::try_from(read_u64()?).ok()
+            .and_then(|sampler| Self::SAMPLERS.get(sampler))
+            .ok_or_else(|| invalid("unknown sampler in the film checkpoint"))?;
+
+        // Checked against the file length before anything is allocated for the pixels.
+        let pixel_count = width.checked_mul(height)
+            .filter(|&count| count.checked_mul(Self::PIXEL_BYTES).and_then(|bytes| bytes.checked_add(Self::HEADER_BYTES)) == Some(file_len))
+            .and_then(|count| usize::try_from(count).ok())
+            .ok_or_else(|| invalid("the film checkpoint size doesn't match its resolution"))?;
+        let (width, height) = (width as usize, height as usize);
+
+        let mut pixels = Vec::with_capacity(pixel_count);
+        for _ in 0..pixel_count {
+            let mut values = [0_f64; 5];
+            for value in values.iter_mut() {
+                *value = f64::from_bits(read_u64()?);
+            }
+            let [r, g, b, mean_luminance, m2] = values;
+            let count = read_u64()? as usize;
+            pixels.push(PixelEstimate { sum: Color::new(r, g, b), count, mean_luminance, m2 });
+        }
+
+        Ok(Self { width, height, seed, sampler, pixels })
+    }
+
+    /// Averages the samples of each pixel.
+    pub(super) fn hdr_image(&self, exposure_scale: f64) -> HdrImage {
+        let pixels = self.pixels.iter()
+            .map(|pixel| pixel.sum * (exposure_scale / pixel.count.max(1) as f64))
+            .collect();
+        HdrImage::new(self.width, self.height, pixels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("film-{}-{name}", std::process::id()))
+    }
+
+    fn film() -> Film {
+        let mut film = Film::new(3, 2, 42, SamplerKind::Sobol);
+        film.pixels[4] = PixelEstimate { sum: Color::new(1., 2., 3.), count: 7, mean_luminance: 0.5, m2: 0.25 };
+        film
+    }
+
+    #[test]
+    fn round_trips() {
+        let path = temp_path("round-trip.film");
+        film().save(&path).unwrap();
+        let loaded = Film::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!((loaded.width, loaded.height, loaded.seed, loaded.sampler), (3, 2, 42, SamplerKind::Sobol));
+        let pixel = loaded.pixels[4];
+        assert_eq!((pixel.sum, pixel.count, pixel.mean_luminance, pixel.m2), (Color::new(1., 2., 3.), 7, 0.5, 0.25));
+        assert_eq!(loaded.pixels[0].count, 0);
+    }
+
+    #[test]
+    fn saves_next_to_the_full_file_name() {
+        // A path already ending in .tmp must not be its own temporary file.
+        let path = temp_path("checkpoint.tmp");
+        film().save(&path).unwrap();
+        assert!(Film::load(&path).is_ok());
+        assert!(!temp_path("checkpoint.tmp.tmp").exists());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rejects_truncated_and_mismatched_files() {
+        let path = temp_path("corrupt.film");
+        film().save(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+
+        let load = |bytes: &[u8]| {
+            fs::write(&path, bytes).unwrap();
+            Film::load(&path)
+        };
+        assert!(load(&bytes[..bytes.len() - 1]).is_err());
+        assert!(load(&bytes[..20]).is_err());
+
+        let mut wrong_magic = bytes.clone();
+        wrong_magic[7] = b'1';
+        assert!(load(&wrong_magic).is_err());
+
+        let mut huge = bytes.clone();
+        huge[8..16].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(load(&huge).is_err());
+
+        let mut unknown_sampler = bytes.clone();
+        unknown_sampler[32..40].copy_from_slice(&99_u64.to_le_bytes());
+        assert!(load(&unknown_sampler).is_err());
+
+        assert!(load(&bytes).is_ok());
+        fs::remove_file(&path).unwrap();
+    }
+}
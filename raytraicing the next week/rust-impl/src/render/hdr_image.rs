@@ -0,0 +1,168 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    color::{write_color_to_pixel_buff, Color},
+    render::{aov::AovImage, pixel_buff::PixelBuff, tone_map::ToneMapping},
+};
+
+/// Linear RGB image, before it is clamped to bytes. Rows go from top to bottom.
+#[derive(Debug, Clone)]
+pub struct HdrImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl HdrImage {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height, "an image needs one color per pixel");
+        Self { width, height, pixels }
+    }
+
+    /// Tone maps and converts to sRGB bytes, resizing `pixel_buff` when needed.
+    pub fn develop(&self, tone_mapping: &ToneMapping, pixel_buff: &mut PixelBuff) {
+        if pixel_buff.pixels.len() <= self.height * self.width {
+            *pixel_buff = PixelBuff::zeroed(self.height, self.width);
+        }
+        for (pos, &color) in self.pixels.iter().enumerate() {
+            write_color_to_pixel_buff(&mut pixel_buff.pixels, pos, tone_mapping.apply(color));
+        }
+    }
+
+    /// Portable float map: a text header, then little endian f32 RGB rows from bottom to top.
+    pub fn write_into_pfm(&self, file: &mut File) -> io::Result<()> {
+        let mut writer = BufWriter::new(file);
+        write!(writer, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        for row in self.pixels.chunks(self.width.max(1)).rev() {
+            for color in row {
+                for c in [color.x(), color.y(), color.z()] {
+                    writer.write_all(&(c as f32).to_le_bytes())?;
+                }
+            }
+        }
+        writer.flush()
+    }
+
+    /// Reads color (PF) and grayscale (Pf) float maps, of either endianness.
+    pub fn load_pfm<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut bytes = vec![];
+        BufReader::new(File::open(path)?).read_to_end(&mut bytes)?;
+        Self::parse_pfm(&bytes)
+    }
+
+    fn parse_pfm(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        // Four whitespace separated tokens, the last one followed by a single whitespace.
+        let mut tokens = vec![];
+        let mut pos = 0;
+        while tokens.len() < 4 {
+            while bytes.get(pos).is_some_and(|b| b.is_ascii_whitespace()) {
+                pos += 1;
+            }
+            let start = pos;
+            while bytes.get(pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+                pos += 1;
+            }
+            if start == pos {
+                return Err(invalid("truncated PFM header"));
+            }
+            tokens.push(String::from_utf8_lossy(&bytes[start..pos]).into_owned());
+        }
+        let data = &bytes[(pos + 1).min(bytes.len())..];
+
+        let channels = match tokens[0].as_str() {
+            "PF" => 3,
+            "Pf" => 1,
+            _ => return Err(invalid("not a PFM file")),
+        };
+        let parse = |token: &str| token.parse::<usize>().map_err(|_| invalid("invalid PFM size"));
+        let (width, height) = (parse(&tokens[1])?, parse(&tokens[2])?);
+        let scale = tokens[3].parse::<f64>().map_err(|_| invalid("invalid PFM scale"))?;
+        // Sizes come from the file, the products may overflow.
+        let pixel_count = width.checked_mul(height)
+            .filter(|count| count.checked_mul(4 * channels).is_some_and(|bytes| bytes <= data.len()))
+            .ok_or_else(|| invalid("truncated PFM data"))?;
+
+        let value = |i: usize| {
+            let b = [data[4 * i], data[4 * i + 1], data[4 * i + 2], data[4 * i + 3]];
+            (if scale < 0. { f32::from_le_bytes(b) } else { f32::from_be_bytes(b) }) as f64
+        };
+        let mut pixels = Vec::with_capacity(pixel_count);
+        for j in (0..height).rev() {
+            for i in 0..width {
+                let k = channels * (j * width + i);
+                pixels.push(if channels == 3 {
+                    Color::new(value(k), value(k + 1), value(k + 2))
+                } else {
+                    Color::new(value(k), value(k), value(k))
+                });
+            }
+        }
+        Ok(Self { width, height, pixels })
+    }
+}
+
+impl From<AovImage> for HdrImage {
+    fn from(aov: AovImage) -> Self {
+        Self { width: aov.width, height: aov.height, pixels: aov.pixels }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pfm(header: &str, values: &[f32], little_endian: bool) -> Vec<u8> {
+        let mut bytes = header.as_bytes().to_vec();
+        for v in values {
+            bytes.extend(if little_endian { v.to_le_bytes() } else { v.to_be_bytes() });
+        }
+        bytes
+    }
+
+    #[test]
+    fn reads_both_byte_orders() {
+        // Two rows of two pixels, stored from the bottom row up.
+        let values = [1., 2., 3., 4., 5., 6., 7., 8., 9., 10., 11., 12.];
+        for (scale, little_endian) in [("-1.0", true), ("1.0", false)] {
+            let image = HdrImage::parse_pfm(&pfm(&format!("PF\n2 2\n{scale}\n"), &values, little_endian)).unwrap();
+            assert_eq!((image.width, image.height), (2, 2));
+            assert_eq!(image.pixels[0], Color::new(7., 8., 9.));
+            assert_eq!(image.pixels[3], Color::new(4., 5., 6.));
+        }
+    }
+
+    #[test]
+    fn reads_grayscale() {
+        let image = HdrImage::parse_pfm(&pfm("Pf\n2 1\n-1.0\n", &[0.5, 2.], true)).unwrap();
+        assert_eq!(image.pixels, vec![Color::new(0.5, 0.5, 0.5), Color::new(2., 2., 2.)]);
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let bytes = pfm("PF\n2 1\n-1.0\n", &[1., 2., 3., 4., 5., 6.], true);
+        assert!(HdrImage::parse_pfm(&bytes).is_ok());
+        assert!(HdrImage::parse_pfm(&bytes[..bytes.len() - 1]).is_err());
+        assert!(HdrImage::parse_pfm(b"PF\n2 1").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        for header in ["P6\n1 1\n-1.0\n", "PF\n-1 1\n-1.0\n", "PF\nx 1\n-1.0\n", "PF\n1 1\nscale\n"] {
+            assert!(HdrImage::parse_pfm(&pfm(header, &[1., 2., 3.], true)).is_err(), "{header:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_dimensions() {
+        for size in ["18446744073709551615 2", "4294967296 4294967296", "1000000 1000000"] {
+            let bytes = pfm(&format!("PF\n{size}\n-1.0\n"), &[1., 2., 3.], true);
+            assert!(HdrImage::parse_pfm(&bytes).is_err(), "{size}");
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use crate::{
+    light::ray::Ray,
+    math::{dot, normalize, random_in_unit_disk, refract, Point3, Sampler, Vec3},
+};
+
+/// One row of a lens prescription table, in millimeters. Rows go from the object side to the
+/// film side, as in lens patents.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LensElement {
+    pub curvature_radius: f64,  // Positive when the center is toward the film, 0 for the aperture stop
+    pub thickness: f64,         // Distance along the axis to the next surface
+    pub ior: f64,               // Index of refraction of the medium after the surface, 0 or 1 for air
+    pub aperture_diameter: f64,
+}
+
+impl LensElement {
+    pub const fn new(curvature_radius: f64, thickness: f64, ior: f64, aperture_diameter: f64) -> Self {
+        Self { curvature_radius, thickness, ior, aperture_diameter }
+    }
+
+    fn medium_ior(&self) -> f64 {
+        if self.ior == 0. { 1. } else { self.ior }
+    }
+}
+
+/// A sequence of spherical lens elements rays are traced through, from the film to the scene,
+/// giving natural vignetting, distortion and focus breathing.
+///
+/// In the lens frame the optical axis is z, the film lies at z = 0 and the scene toward +z.
+pub struct LensSystem {
+    elements: Vec<LensElement>,
+    film_diagonal: f64,      // In millimeters
+    world_units_per_mm: f64, // 0.001 for a scene in meters
+}
+
+impl LensSystem {
+    pub fn new(elements: Vec<LensElement>, film_diagonal: f64) -> Self {
+        assert!(!elements.is_empty(), "a lens system needs at least one element");
+        Self { elements, film_diagonal, world_units_per_mm: 0.001 }
+    }
+
+    pub fn with_world_units_per_mm(mut self, world_units_per_mm: f64) -> Self {
+        self.world_units_per_mm = world_units_per_mm;
+        self
+    }
+
+    /// Double Gauss 50mm f/2 (US patent 2,673,491), on a 35mm film.
+    pub fn double_gauss_50mm() -> Self {
+        Self::new(
+            vec![
+                LensElement::new(29.475, 3.76, 1.67, 25.2),
+                LensElement::new(84.83, 0.12, 1., 25.2),
+                LensElement::new(19.275, 4.025, 1.67, 23.),
+                LensElement::new(40.77, 3.275, 1.699, 23.),
+                LensElement::new(12.75, 5.705, 1., 18.),
+                LensElement::new(0., 4.5, 0., 17.1),
+                LensElement::new(-14.495, 1.18, 1.603, 17.),
+                LensElement::new(40.77, 6.065, 1.658, 20.),
+                LensElement::new(-20.385, 0.19, 1., 20.),
+                LensElement::new(437.065, 3.22, 1.717, 20.),
+                LensElement::new(-39.73, 0., 1., 20.),
+            ],
+            43.27,
+        )
+    }
+
+    /// Vertex positions along the axis of every surface, for a given film to rear vertex distance.
+    fn surfaces_z(&self, film_distance: f64) -> Vec<f64> {
+        let mut z = vec![film_distance; self.elements.len()];
+        for i in (0..self.elements.len() - 1).rev() {
+            z[i] = z[i + 1] + self.elements[i].thickness;
+        }
+        z
+    }
+
+    /// Intersects the ray with the surface `i` and refracts it into the medium on the other
+    /// side, `to_film` giving the direction of travel. None when the ray is blocked.
+    fn refract_at(&self, i: usize, z: f64, origin: Point3, dir: Vec3, to_film: bool) -> Option<(Point3, Vec3)> {
+        let element = self.elements[i];
+        let (t, normal) = if element.curvature_radius == 0. {
+            if dir.z() == 0. {
+                return None;
+            }
+            ((z - origin.z()) / dir.z(), Vec3::new(0., 0., 1.))
+        } else {
+            let center = Point3::new(0., 0., z - element.curvature_radius);
+            let oc = center - origin;
+            let a = dir.length_sq();
+            let h = dot(dir, oc);
+            let c = oc.length_sq() - element.curvature_radius * element.curvature_radius;
+            let discriminant = h * h - a * c;
+            if discriminant < 0. {
+                return None;
+            }
+
+            // Keep the cap of the sphere around the vertex.
+            let sqrtd = discriminant.sqrt();
+            let (t0, t1) = ((h - sqrtd) / a, (h + sqrtd) / a);
+            let near_vertex = |t: f64| (origin + t * dir).z() - z;
+            let t = if near_vertex(t0).abs() < near_vertex(t1).abs() { t0 } else { t1 };
+            (t, normalize(origin + t * dir - center))
+        };
+        if t <= 0. {
+            return None;
+        }
+
+        let hit = origin + t * dir;
+        let radius = element.aperture_diameter / 2.;
+        if hit.x() * hit.x() + hit.y() * hit.y() > radius * radius {
+            return None;
+        }
+        if element.curvature_radius == 0. {
+            return Some((hit, dir));
+        }
+
+        // Indices on both sides of the surface, air being in front of the first one.
+        let before = if i == 0 { 1. } else { self.elements[i - 1].medium_ior() };
+        let after = element.medium_ior();
+        let eta = if to_film { before / after } else { after / before };
+
+        let unit_dir = normalize(dir);
+        let n = if dot(normal, unit_dir) > 0. { -normal } else { normal };
+        let cos_theta = dot(-unit_dir, n).min(1.);
+        if eta * eta * (1. - cos_theta * cos_theta) > 1. {
+            return None; // Total internal reflection
+        }
+        Some((hit, refract(unit_dir, n, eta)))
+    }
+
+    /// Distance from the rear vertex to the film that focuses at `focus_dist` (in world units)
+    /// in front of the lens, found by tracing a paraxial ray from the scene.
+    pub fn focus(&self, focus_dist: f64) -> Option<f64> {
+        let z = self.surfaces_z(0.);
+        let height = 0.01 * self.elements[0].aperture_diameter;
+        let mut origin = Point3::new(0., 0., z[0] + focus_dist / self.world_units_per_mm);
+        let mut dir = Point3::new(height, 0., z[0]) - origin;
+
+        for (i, &zi) in z.iter().enumerate() {
+            (origin, dir) = self.refract_at(i, zi, origin, dir, true)?;
+        }
+
+        // Where the ray crosses the axis behind the rear element.
+        if dir.x() == 0. {
+            return None;
+        }
+        let t = -origin.x() / dir.x();
+        let film_distance = -(origin + t * dir).z();
+        (t > 0. && film_distance > 0.).then_some(film_distance)
+    }
+
+    /// Traces a ray from the film point (x, y), in millimeters, through the lens. Returns the
+    /// ray leaving the front element in the lens frame, scaled to world units, the front
+    /// vertex being at the origin. None when the lens blocks it.
+    pub fn trace_from_film(&self, film_distance: f64, (x, y): (f64, f64), sampler: &mut dyn Sampler) -> Option<Ray> {
+        let z = self.surfaces_z(film_distance);
+        let last = self.elements.len() - 1;
+
+        // Aim at a random point of the rear element.
+        let rear_radius = self.elements[last].aperture_diameter / 2.;
+        let p = rear_radius * random_in_unit_disk(sampler);
+        let mut origin = Point3::new(x, y, 0.);
+        let mut dir = Point3::new(p.x(), p.y(), z[last]) - origin;
+
+        // Irradiance falls off with cos^4 toward the film corners, handled by roulette.
+        let cos_theta = normalize(dir).z();
+        if sampler.get_1d() > cos_theta.powi(4) {
+            return None;
+        }
+
+        for i in (0..=last).rev() {
+            (origin, dir) = self.refract_at(i, z[i], origin, dir, false)?;
+        }
+
+        let origin = Point3::new(origin.x(), origin.y(), origin.z() - z[0]);
+        Some(Ray::new(self.world_units_per_mm * origin, dir))
+    }
+
+    /// Film size in millimeters for an image with the given aspect ratio.
+    pub fn film_size(&self, aspect_ratio: f64) -> (f64, f64) {
+        let height = self.film_diagonal / f64::sqrt(1. + aspect_ratio * aspect_ratio);
+        (height * aspect_ratio, height)
+    }
+}
@@ -1,69 +1,291 @@
-use std::io::{stdout, Write};
-
-use crate::{color::{write_color_to_pixel_buff, Color, BLACK}, light::{ray::Ray, HitRecord, Hittable}, math::{cross, deg_to_rad, normalize, random_in_unit_disk, Intervall, Point3, Vec3}, render::pixel_buff::PixelBuff};
+use std::{cell::Cell, f64::consts::PI, fs::File, io, path::Path, rc::Rc, time::Instant};
+
+use crate::{color::{write_color_to_pixel_buff, Color, BLACK, WHITE}, light::{lights::LightSample, material::ScatterRecord, ray::Ray, HitRecord, Hittable}, scene::Scene, math::{cross, deg_to_rad, dot, lerp, normalize, power_heuristic, Intervall, Point3, Sampler, SamplerKind, Vec3}, render::{adaptive::AdaptiveSampling, aov::{Aov, AovFilm, AovImage, AovSample, AovSet, AovVertex}, animation::{CameraAnimation, CameraKeyframe}, aperture::{Aperture, PhysicalExposure}, denoise::Denoiser, film::Film, hdr_image::HdrImage, lens_system::LensSystem, limits::{RenderLimits, RenderOutcome}, pixel_buff::PixelBuff, progress::{Progress, ProgressObserver, SilentProgress}, progressive::Progressive, projection::{FisheyeMapping, Projection}, stats::Counter, stereo::StereoRig, tone_map::ToneMapping}};
+pub mod adaptive;
+pub mod aov;
+pub mod animation;
+pub mod aperture;
+pub mod denoise;
+pub mod film;
+pub mod hdr_image;
+pub mod lens_system;
+pub mod limits;
 pub mod pixel_buff;
+pub mod progress;
+pub mod progressive;
+pub mod projection;
+pub mod stats;
+pub mod stereo;
+pub mod tone_map;
+
+
+#[derive(Debug, Clone, Copy)]
+struct CamFrameBasis {
+    u: Vec3, // Right
+    v: Vec3, // Up
+    w: Vec3, // Opposite of the view direction
+}
+
+/// The part of the camera set by its pose, from which rays are generated.
+#[derive(Debug, Clone, Copy)]
+struct RayFrame {
+    center              :Point3,        // Camera center
+    pixel00_loc         :Point3,        // Location of pixel 0, 0
+    pixel_delta_u       :Vec3,          // Offset to pixel to the right
+    pixel_delta_v       :Vec3,          // Offset to pixel below
+    basis               :CamFrameBasis, // Camera frame basis vectors
+    defocus_disk_u      :Vec3,          // Defocus disk horizontal radius
+    defocus_disk_v      :Vec3,          // Defocus disk vertical radius
+    focus_dist          :f64,           // Distance to the plane of focus, toward the focus target when there is one
+    lens_film_distance  :f64,           // Distance from the rear lens element to the film, set by focusing
+    focus_plane_normal  :Vec3,          // Normal of the plane of focus, w when it isn't tilted
+}
 
+impl RayFrame {
+    // Placeholder until the camera is posed, its NaN focus distance matching no other.
+    const UNSET: Self = Self {
+        center: Point3::ZERO,
+        pixel00_loc: Point3::ZERO,
+        pixel_delta_u: Vec3::ZERO,
+        pixel_delta_v: Vec3::ZERO,
+        basis: CamFrameBasis { u: Vec3::ZERO, v: Vec3::ZERO, w: Vec3::ZERO },
+        defocus_disk_u: Vec3::ZERO,
+        defocus_disk_v: Vec3::ZERO,
+        focus_dist: f64::NAN,
+        lens_film_distance: 0.,
+        focus_plane_normal: Vec3::ZERO,
+    };
+}
 
 pub struct Camera{
     // -- Public attributs --
     pub aspect_ratio     :f64, // = 1.0;  // Ratio of image width over height
     pub image_width      :usize, // = 100;  // Rendered image width in pixel count
     pub samples_per_pixel:usize, // = 10;   // Count of random samples for each pixel
+    pub sampler          :SamplerKind, // = Independent; // How samples are spread over the pixel, lens, time and bounce dimensions
+    pub adaptive         :Option<AdaptiveSampling>, // = None; // Stops converged pixels early, replacing samples_per_pixel when set
+    pub progressive      :Option<Progressive>, // = None; // Renders in passes over the whole image, with checkpoints
+    pub progress         :Rc<dyn ProgressObserver>, // = SilentProgress; // Told how far renders are
+    pub aovs             :AovSet, // = none; // Auxiliary buffers (albedo, normal, depth...) rendered with the image
+    pub denoiser         :Option<Denoiser>, // = None; // Filters the image after rendering, guided by the albedo and normal AOVs
     pub max_depth        :usize, // = 10;   // Maximum number of ray bounces into scene
 
     pub vfov    :f64,    // = 90;              // Vertical view angle (field of view)
     pub lookfrom:Point3, // = point3(0,0,0);   // Point camera is looking from
     pub lookat  :Point3, // = point3(0,0,-1);  // Point camera is looking at
     pub vup     :Vec3,   // = vec3(0,1,0);    // Camera-relative "up" direction
+    pub shift   :(f64,f64), // = (0,0);       // Lens shift, in viewport widths (right) and heights (up)
 
     pub defocus_angle:f64, // = 0;  // Variation angle of rays through each pixel
     pub focus_dist   :f64, // = 10;    // Distance from camera lookfrom point to plane of perfect focus
+    pub focus_target :Option<Point3>, // = None; // Point to focus on, overrides focus_dist when set
+    pub tilt         :(f64,f64), // = (0,0); // Focus plane tilt and swing in degrees, bringing it closer at the bottom and left of the image
+    pub aperture     :Aperture, // = Circular; // Shape of the defocus disk
+    pub exposure     :Option<PhysicalExposure>, // = None; // f-stop, shutter and ISO scaling of the image
+    pub tone_mapping :ToneMapping, // = Clamp, 0 EV; // Exposure compensation and curve applied to the 8 bit output
+    pub lens         :Option<Rc<LensSystem>>, // = None; // Traces through real lens elements instead of the thin lens, vfov and defocus_angle being ignored
+
+    pub projection   :Projection, // = Perspective; // How rays are laid out over the image
+    pub stereo       :Option<StereoRig>, // = None;  // Renders both eyes in one image when set
+
+    pub animation    :Option<Rc<CameraAnimation>>, // = None; // Keyframed path overriding lookfrom, lookat, vfov and focus_dist
+    pub time         :f64, // = 0;     // Time at which the shutter opens, in seconds
 
     // -- Private attributs --
     image_height        :usize,           // Rendered image height
-    pixel_samples_scale :f64,           // Color scale factor for a sum of pixel samples
-    center              :Point3,        // Camera center
-    pixel00_loc         :Point3,        // Location of pixel 0, 0
-    pixel_delta_u       :Vec3,          // Offset to pixel to the right
-    pixel_delta_v       :Vec3,          // Offset to pixel below
-    // basis               :CamFrameBasis, // Camera frame basis vectors
-    defocus_disk_u      :Vec3,          // Defocus disk horizontal radius³
-    defocus_disk_v      :Vec3,          // Defocus disk vertical radius
+    exposure_scale      :f64,           // Color scale factor of the exposure
+    film                :Film,          // Samples accumulated for each pixel by the last render
+    aov_film            :AovFilm,       // AOV samples of the last render
+    ray_count           :Cell<u64>,     // Rays traced by the current render
+    frame               :RayFrame,      // Camera center, basis, viewport and focus for the current pose
 }
 
 impl Camera {
-    pub fn render(&mut self,world: &dyn Hittable,pixel_buff :&mut PixelBuff){
+    pub fn render(&mut self,scene: &Scene,pixel_buff :&mut PixelBuff)->io::Result<()>{
+        self.render_until(scene, pixel_buff, &RenderLimits::default()).map(|_| ())
+    }
+
+    /// Renders until every pixel got its samples or one of the `limits` fires. Unless the
+    /// camera is progressive, passes of one sample per pixel are used so the image is
+    /// evenly refined when stopped.
+    /// Fails when a checkpoint can't be saved, the image being developed all the same.
+    pub fn render_until(&mut self,scene: &Scene,pixel_buff :&mut PixelBuff,limits:&RenderLimits)->io::Result<RenderOutcome>{
+        self.init();
+
+        let (out_width, out_height) = self.output_size();
+        self.film = Film::new(out_width, out_height, scene.seed, self.sampler);
+        self.aov_film = AovFilm::new(self.aov_list(scene), out_width, out_height);
+        let outcome = self.render_passes(scene, limits);
+        self.develop(pixel_buff);
+        outcome
+    }
+
+    /// Continues the render saved in `checkpoint`, up to the current sample count, which may
+    /// be higher than the one it was started with, or until one of the `limits` fires.
+    pub fn resume(&mut self,scene: &Scene,pixel_buff :&mut PixelBuff,checkpoint:&Path,limits:&RenderLimits)->io::Result<RenderOutcome>{
         self.init();
-        //init pixel_buff
-        if pixel_buff.pixels.len() <= self.image_height * self.image_width{
-            *pixel_buff = PixelBuff::zeroed(self.image_height, self.image_width);
-        }
-
-        let nb = self.image_height * self.image_width * self.samples_per_pixel;
-        let step = nb / 100;
-        let mut iter_nb = 0;
-
-        print!("[");
-        for j in 0..self.image_height{
-            for i in 0..self.image_width{
-                let mut pixel_color = Color::ZERO;
-                for _sample in 0..self.samples_per_pixel{
-                    iter_nb += 1;
-                    if iter_nb % step == 0{
-                        print!("#");
-                        let _ = stdout().flush();
+
+        let film = Film::load(checkpoint)?;
+        if (film.width, film.height) != self.output_size(){
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the checkpoint size doesn't match the camera"));
+        }
+        if (film.seed, film.sampler) != (scene.seed, self.sampler){
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the checkpoint was rendered with another seed or sampler"));
+        }
+        self.film = film;
+        // AOVs aren't checkpointed, they come from the samples added from now on.
+        let (out_width, out_height) = self.output_size();
+        self.aov_film = AovFilm::new(self.aov_list(scene), out_width, out_height);
+        let outcome = self.render_passes(scene, limits);
+        self.develop(pixel_buff);
+        outcome
+    }
+
+    /// The image of the last render, before denoising and clamping.
+    pub fn hdr_image(&self)->HdrImage{
+        self.film.hdr_image(self.exposure_scale)
+    }
+
+    fn develop(&self,pixel_buff :&mut PixelBuff){
+        let image = self.hdr_image();
+        match &self.denoiser {
+            None => image.develop(&self.tone_mapping, pixel_buff),
+            Some(denoiser) => {
+                let albedo = self.aov(Aov::Albedo).map(HdrImage::from);
+                let normal = self.aov(Aov::Normal).map(HdrImage::from);
+                denoiser.denoise(&image, albedo.as_ref(), normal.as_ref()).develop(&self.tone_mapping, pixel_buff);
+            }
+        }
+    }
+
+    fn aov_list(&self,scene: &Scene)->Vec<Aov>{
+        // The denoiser needs its guides even when they weren't asked for.
+        let mut aovs = self.aovs;
+        if self.denoiser.is_some() {
+            (aovs.albedo, aovs.normal) = (true, true);
+        }
+        aovs.list(scene.lights.len())
+    }
+
+    fn checkpoint_error(path:&Path, e:io::Error)->io::Error{
+        io::Error::new(e.kind(), format!("can't save the checkpoint {}: {e}", path.display()))
+    }
+
+    fn output_size(&self)->(usize,usize){
+        match self.stereo {
+            None => (self.image_width, self.image_height),
+            Some(rig) => rig.output_size(self.image_width, self.image_height),
+        }
+    }
+
+    fn render_passes(&mut self,scene: &Scene,limits:&RenderLimits)->io::Result<RenderOutcome>{
+        // Adds samples to the film, pass after pass, until every pixel has enough of them
+        // or a limit fires. The first checkpoint that couldn't be saved fails it once done.
+
+        // Each eye is a (signed offset along u, position of its view in the image) pair.
+        let eyes = match self.stereo {
+            None => vec![(0., (0, 0))],
+            Some(rig) => rig.eyes(self.image_width, self.image_height).to_vec(),
+        };
+        let (out_width, out_height) = self.output_size();
+
+        // With motion blur, each sample sees the camera somewhere along its path while the shutter is open.
+        let shutter = self.animation.as_ref().map_or(0., |animation| animation.shutter_duration());
+
+        let max_samples = self.adaptive.map_or(self.samples_per_pixel, |adaptive| adaptive.max_samples);
+        let default_pass = if limits.is_unlimited() { max_samples } else { 1 };
+        let pass_samples = self.progressive.as_ref().map_or(default_pass, |progressive| progressive.samples_per_pass).max(1);
+        let passes = max_samples.div_ceil(pass_samples);
+
+        // Resumed films already have some of the samples.
+        let pixel_count = (eyes.len() * self.image_height * self.image_width) as u64;
+        let done_samples: u64 = self.film.pixels.iter().map(|pixel| pixel.count.min(max_samples) as u64).sum();
+        let mut progress = Progress {
+            completed_samples: 0,
+            total_samples: pixel_count * max_samples as u64 - done_samples,
+            rays: 0,
+            elapsed: Default::default(),
+        };
+        let start = Instant::now();
+        self.ray_count.set(0);
+        let mut last_checkpoint = start;
+
+        let mut sampler = self.sampler.create(scene.seed, max_samples, (out_width, out_height));
+        let mut aov_sample = (!self.aov_film.is_empty()).then(|| AovSample::new(scene.lights.len()));
+        let mut outcome = RenderOutcome::Completed;
+        let mut checkpoint_error = None;
+
+        self.progress.on_start(progress.total_samples);
+        'passes: for pass in 0..passes{
+            let pass_end = ((pass + 1) * pass_samples).min(max_samples);
+            for &(eye, (view_i, view_j)) in eyes.iter(){
+                for j in 0..self.image_height{
+                    for i in 0..self.image_width{
+                        if let Some(stop) = limits.check(){
+                            outcome = stop;
+                            break 'passes;
+                        }
+
+                        let pixel = (view_i + i, view_j + j);
+                        let pos = pixel.0 + pixel.1*out_width;
+                        let mut estimate = self.film.pixels[pos];
+                        let count_before = estimate.count;
+                        while estimate.count < pass_end && !self.adaptive.is_some_and(|adaptive| adaptive.is_converged(&estimate)){
+                            sampler.start_pixel_sample(pixel, estimate.count);
+                            let frame = match &self.animation {
+                                Some(animation) if shutter > 0. => self.ray_frame(&animation.pose_at(self.time + sampler.get_1d() * shutter)),
+                                _ => self.frame,
+                            };
+                            if let Some(aov_sample) = aov_sample.as_mut() {
+                                aov_sample.reset();
+                            }
+                            let sample_color = match self.get_ray(&frame, (i,j), eye, sampler.as_mut()) {
+                                Some(mut ray) => self.ray_color(&mut ray, self.max_depth, scene, None, sampler.as_mut(), aov_sample.as_mut().map(AovVertex::Camera)),
+                                None => BLACK,
+                            };
+                            estimate.add(sample_color);
+                            if let Some(aov_sample) = &aov_sample {
+                                self.aov_film.add(pos, aov_sample);
+                            }
+                        }
+                        self.film.pixels[pos] = estimate;
+
+                        progress.completed_samples += (estimate.count - count_before) as u64;
+                        progress.rays = self.ray_count.get();
+                        progress.elapsed = start.elapsed();
+                        self.progress.on_progress(&progress);
                     }
-                    let mut ray = self.get_ray((i,j));
-                    pixel_color += self.ray_color(&mut ray, self.max_depth, world);
                 }
-                let pos = i + j*self.image_width;
-                write_color_to_pixel_buff(&mut pixel_buff.pixels, pos, pixel_color * self.pixel_samples_scale);
             }
+
+            if let Some(Progressive { checkpoint: Some(path), checkpoint_interval, .. }) = &self.progressive
+                && (pass + 1 == passes || last_checkpoint.elapsed() >= *checkpoint_interval) {
+                // Rendering goes on, the next checkpoint may succeed.
+                if let Err(e) = self.film.save(path) {
+                    checkpoint_error.get_or_insert(Self::checkpoint_error(path, e));
+                }
+                last_checkpoint = Instant::now();
+            }
+        }
+        progress.rays = self.ray_count.get();
+        progress.elapsed = start.elapsed();
+        self.progress.on_finish(&progress, outcome);
+
+        // Keep what a stopped render did, so it can be resumed.
+        if let (RenderOutcome::DeadlineReached | RenderOutcome::Cancelled, Some(Progressive { checkpoint: Some(path), .. })) = (outcome, &self.progressive)
+            && let Err(e) = self.film.save(path) {
+            checkpoint_error.get_or_insert(Self::checkpoint_error(path, e));
+        }
+        match checkpoint_error {
+            Some(e) => Err(e),
+            None => Ok(outcome),
         }
-        println!("]")
     }
 
 
+    /// Camera with the optical setup given, the other public fields keeping their default
+    /// values until set.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         aspect_ratio     :f64,
         image_width      :usize,
@@ -73,18 +295,70 @@ impl Camera {
         lookfrom         :Point3,
         lookat           :Point3,
         vup              :Vec3,
+        shift            :(f64,f64),
         defocus_angle    :f64,
         focus_dist       :f64,
+        focus_target     :Option<Point3>,
+        tilt             :(f64,f64),
+        aperture         :Aperture,
+        exposure         :Option<PhysicalExposure>,
+        lens             :Option<Rc<LensSystem>>,
+        projection       :Projection,
+        stereo           :Option<StereoRig>,
     ) -> Self{
-        let d_image_width = image_width as f64;
-        
-        // Calculate the image height, and ensure that it's at least 1.
-        let image_height = ((d_image_width / aspect_ratio)).max(0.) as usize;
-        let d_image_height = image_height as f64;
-        let pixel_samples_scale = (samples_per_pixel as f64).recip();
+        let mut camera = Self{
+            aspect_ratio,
+            image_width,
+            samples_per_pixel,
+            sampler: SamplerKind::Independent,
+            adaptive: None,
+            progressive: None,
+            progress: Rc::new(SilentProgress),
+            aovs: AovSet::default(),
+            denoiser: None,
+            max_depth,
+            vfov,
+            lookfrom,
+            lookat,
+            vup,
+            shift,
+            defocus_angle,
+            focus_dist,
+            focus_target,
+            tilt,
+            aperture,
+            exposure,
+            tone_mapping: ToneMapping::default(),
+            lens,
+            projection,
+            stereo,
+            animation: None,
+            time: 0.,
+            image_height: 0,
+            exposure_scale: 1.,
+            film: Film::new(0, 0, 0, SamplerKind::Independent),
+            aov_film: AovFilm::new(vec![], 0, 0),
+            ray_count: Cell::new(0),
+            frame: RayFrame::UNSET,
+        };
+        camera.init();
+        camera
+    }
+
+    fn ray_frame(&self, pose:&CameraKeyframe)->RayFrame{
+        // Only what the pose changes, cheap enough to be done for each motion blurred sample.
+        let CameraKeyframe { lookfrom, lookat, vfov, focus_dist, .. } = *pose;
+        let (d_image_width, d_image_height) = (self.image_width as f64, self.image_height as f64);
+        let (vup, shift, tilt) = (self.vup, self.shift, self.tilt);
 
         let center = lookfrom;
 
+        // Focus on the target point when there is one.
+        let focus_dist = match self.focus_target {
+            Some(target) => dot(target - lookfrom, normalize(lookat - lookfrom)).max(1e-3),
+            None => focus_dist,
+        };
+
         // Determine viewport dimensions.
         let theta = deg_to_rad(vfov);
         let h = f64::tan(theta / 2.0);
@@ -95,7 +369,7 @@ impl Camera {
         let w = normalize(lookfrom - lookat);
         let u = normalize(cross(vup, w));
         let v = cross(w, u);
-        // let basis = CamFrameBasis{ u, v, w };
+        let basis = CamFrameBasis{ u, v, w };
 
         // Calculate the vectors across the horizontal and down the vertical viewport edges
         let viewport_u = viewport_width * u;    // Vector across viewport horizontal edge
@@ -105,40 +379,90 @@ impl Camera {
         let pixel_delta_u = viewport_u / d_image_width;
         let pixel_delta_v = viewport_v / d_image_height;
 
-        // Calculate the location of the upper left pixel.
-        let viewport_upper_left = center - (focus_dist * w) - viewport_u/2. - viewport_v/2.;
+        // Calculate the location of the upper left pixel, the viewport being off-center when shifted.
+        let viewport_upper_left = center - (focus_dist * w) - viewport_u/2. - viewport_v/2.
+            + shift.0 * viewport_u - shift.1 * viewport_v;
         let pixel00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
 
         // Calculate the camera defocus disk basis vectors.
-        let defocus_radius = focus_dist * f64::tan(deg_to_rad(defocus_angle / 2.));
+        let defocus_radius = focus_dist * f64::tan(deg_to_rad(self.defocus_angle / 2.));
         let defocus_disk_u = u * defocus_radius;
         let defocus_disk_v = v * defocus_radius;
 
-        Self{
-            aspect_ratio,
-            image_width,
-            samples_per_pixel,
-            max_depth,
-            vfov,
-            lookfrom,
-            lookat,
-            vup,
-            defocus_angle,
-            focus_dist,
-            image_height,
-            pixel_samples_scale,
+        // Tilt the plane of focus around u, then swing it around v.
+        let (tilt_angle, swing_angle) = (deg_to_rad(tilt.0), deg_to_rad(tilt.1));
+        let focus_plane_normal = normalize(w + f64::tan(tilt_angle) * v + f64::tan(swing_angle) * u);
+
+        // Move the film so the lens focuses at focus_dist, or at infinity when it can't get that close.
+        let lens_film_distance = if focus_dist == self.frame.focus_dist {
+            self.frame.lens_film_distance
+        } else {
+            self.lens.as_ref()
+                .and_then(|lens| lens.focus(focus_dist).or_else(|| lens.focus(1e6)))
+                .unwrap_or(0.)
+        };
+
+        RayFrame {
             center,
             pixel00_loc,
             pixel_delta_u,
             pixel_delta_v,
-            // basis,
+            basis,
             defocus_disk_u,
             defocus_disk_v,
+            focus_dist,
+            lens_film_distance,
+            focus_plane_normal,
+        }
+    }
+
+    /// Samples taken for each pixel of the last render, from black for none to white for the
+    /// maximum, through blue, red and yellow.
+    pub fn sample_heatmap(&self)->PixelBuff{
+        let max = self.film.pixels.iter().map(|pixel| pixel.count).max().unwrap_or(0).max(1) as f64;
+        let ramp = [BLACK, Color::new(0., 0., 1.), Color::new(1., 0., 0.), Color::new(1., 1., 0.), WHITE];
+
+        let mut heatmap = PixelBuff::zeroed(self.film.height, self.film.width);
+        for (pos, pixel) in self.film.pixels.iter().enumerate(){
+            let t = pixel.count as f64 / max * (ramp.len() - 1) as f64;
+            let k = (t as usize).min(ramp.len() - 2);
+            write_color_to_pixel_buff(&mut heatmap.pixels, pos, lerp(ramp[k], ramp[k + 1], t - k as f64));
         }
+        heatmap
+    }
+
+    /// An AOV of the last render, None when it wasn't asked for in `aovs`.
+    pub fn aov(&self, aov:Aov)->Option<AovImage>{
+        self.aov_film.image(aov, self.exposure_scale)
     }
 
     fn init(&mut self){
-        *self = Self::new(self.aspect_ratio, self.image_width, self.samples_per_pixel, self.max_depth, self.vfov, self.lookfrom, self.lookat, self.vup, self.defocus_angle, self.focus_dist);
+        // Derives the private fields from the public ones, which may have changed since the last render.
+        // Calculate the image height, and ensure that it's at least 1.
+        self.image_height = (self.image_width as f64 / self.aspect_ratio).max(0.) as usize;
+        self.exposure_scale = self.exposure.map_or(1., |e| e.scale());
+
+        let pose = match &self.animation {
+            Some(animation) => animation.pose_at(self.time),
+            None => CameraKeyframe::new(self.time, self.lookfrom, self.lookat, self.vfov, self.focus_dist),
+        };
+        self.frame = self.ray_frame(&pose);
+    }
+
+    /// Renders every frame of the animation into `dir`, as frame_0001.png and so on.
+    pub fn render_sequence(&mut self,scene: &Scene,dir:&Path)->io::Result<()>{
+        let frames = self.animation.as_ref().map_or(0..=0, |animation| animation.frames());
+        for frame in frames{
+            if let Some(animation) = &self.animation {
+                self.time = animation.frame_time(frame);
+            }
+            let mut pixel_buff = PixelBuff::empty();
+            self.render(scene, &mut pixel_buff)?;
+
+            let mut file = File::create(dir.join(format!("frame_{:04}.png", frame + 1)))?;
+            pixel_buff.write_into_png(&mut file)?;
+        }
+        Ok(())
     }
 }
 
@@ -154,60 +478,241 @@ impl Default for Camera {
             Point3::new(0.,0.,0.), //lookfrom, 
             Point3::new(0.,0.,-1.),  //lookat, 
             Vec3::new(0.,1.,0.),        //vup, 
+            (0., 0.),                   //shift,
 
             0., // defocus_angle:f64,; 
             10.,   // focus_dist   :f64,;
+            None,  // focus_target,
+            (0., 0.), // tilt,
+            Aperture::Circular, // aperture,
+            None,  // exposure,
+            None,  // lens,
+
+            Projection::Perspective, // projection,
+            None,                    // stereo,
         )
     }
 }
 
 
 impl Camera{
-    fn get_ray(&self,(i,j):(usize,usize))->Ray{
-        // Construct a camera ray originating from the defocus disk and directed at a randomly
-        // sampled point around the pixel location i, j.
+    fn get_ray(&self,frame:&RayFrame,(i,j):(usize,usize), eye:f64, sampler:&mut dyn Sampler)->Option<Ray>{
+        // Construct a camera ray directed at a randomly sampled point around the pixel
+        // location i, j, from the eye offset by `eye` along u (0 when not in stereo), with the
+        // camera posed as in `frame`.
+        // Returns None for pixels outside of the projection (fisheye corners).
+
+        let offset = self.sample_square(sampler);
+        let CamFrameBasis { u, v, w } = frame.basis;
+        let eye_center = frame.center + eye * u;
+
+        match self.projection {
+            Projection::Perspective if self.lens.is_some() => {
+                // Film coordinates in [-0.5, 0.5], y going up, flipped as the lens inverts the image.
+                let lens = self.lens.as_ref().unwrap();
+                let x = (i as f64 + 0.5 + offset.x()) / self.image_width as f64 - 0.5;
+                let y = 0.5 - (j as f64 + 0.5 + offset.y()) / self.image_height as f64;
+                let (film_width, film_height) = lens.film_size(self.aspect_ratio);
+
+                let film = (-(x + self.shift.0) * film_width, -(y + self.shift.1) * film_height);
+                let lens_ray = lens.trace_from_film(frame.lens_film_distance, film, sampler)?;
+                let to_world = |p: Vec3| p.x() * u + p.y() * v - p.z() * w;
+                Some(Ray::new(eye_center + to_world(lens_ray.origine), to_world(lens_ray.direction)))
+            }
+            Projection::Perspective => {
+                // The ray originates from the defocus disk.
+                let mut pixel_sample = frame.pixel00_loc
+                        + ((i as f64 + offset.x()) * frame.pixel_delta_u)
+                        + ((j as f64 + offset.y()) * frame.pixel_delta_v);
+
+                let mut pinhole = frame.center;
+                if let (Some(rig), true) = (self.stereo, eye != 0.) {
+                    // Off-axis frustum: both eyes see the same window at the convergence
+                    // distance, the focus plane being kept at focus_dist.
+                    pixel_sample = frame.center + (rig.convergence / frame.focus_dist) * (pixel_sample - frame.center);
+                    pinhole = eye_center;
+                }
 
-        let offset = self.sample_square();
-        let pixel_sample = self.pixel00_loc
-                + ((i as f64 + offset.x()) * self.pixel_delta_u)
-                + ((j as f64 + offset.y()) * self.pixel_delta_v);
+                if self.tilt != (0., 0.) || pinhole != frame.center {
+                    // Move the sample along the pinhole ray onto the plane of focus, which may be tilted.
+                    let dir = pixel_sample - pinhole;
+                    let focus_point = frame.center - frame.focus_dist * w;
+                    let t = dot(focus_point - pinhole, frame.focus_plane_normal) / dot(dir, frame.focus_plane_normal);
+                    if t.is_finite() && t > 0. {
+                        pixel_sample = pinhole + t * dir;
+                    }
+                }
 
-        let ray_org = if self.defocus_angle <= 0. {self.center} else{ self.defocus_disk_sample()};
-        let ray_dir = pixel_sample - ray_org;
+                let ray_org = if self.defocus_angle <= 0. {eye_center} else{ self.defocus_disk_sample(frame, sampler) + eye * u};
+                let ray_dir = pixel_sample - ray_org;
 
-        Ray::new(ray_org, ray_dir)
+                Some(Ray::new(ray_org, ray_dir))
+            }
+            Projection::Orthographic { height } => {
+                // Film coordinates in [-0.5, 0.5], y going up.
+                let x = (i as f64 + 0.5 + offset.x()) / self.image_width as f64 - 0.5 + self.shift.0;
+                let y = 0.5 - (j as f64 + 0.5 + offset.y()) / self.image_height as f64 + self.shift.1;
+                let width = height * self.image_width as f64 / self.image_height as f64;
+
+                Some(Ray::new(eye_center + (x * width) * u + (y * height) * v, -w))
+            }
+            Projection::Fisheye { fov, mapping } => {
+                // Coordinates relative to the image center, the image height spanning [-1, 1].
+                let half_height = self.image_height as f64 / 2.;
+                let x = (i as f64 + 0.5 + offset.x() - self.image_width as f64 / 2.) / half_height;
+                let y = (half_height - (j as f64 + 0.5 + offset.y())) / half_height;
+                let r = f64::sqrt(x * x + y * y);
+                if r > 1. {
+                    return None;
+                }
+
+                let half_fov = deg_to_rad(fov) / 2.;
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => r * half_fov,
+                    FisheyeMapping::Equisolid => 2. * f64::asin((r * f64::sin(half_fov / 2.)).min(1.)),
+                };
+                let phi = f64::atan2(y, x);
+                let dir = theta.sin() * (phi.cos() * u + phi.sin() * v) - theta.cos() * w;
+
+                Some(Ray::new(eye_center, dir))
+            }
+            Projection::Equirectangular => {
+                // Longitude across the image width, latitude down its height.
+                let mut phi = ((i as f64 + 0.5 + offset.x()) / self.image_width as f64 - 0.5) * 2. * PI;
+                let theta = (j as f64 + 0.5 + offset.y()) / self.image_height as f64 * PI;
+
+                // Omni-directional stereo: the eye lies on a circle, offset to the side of the
+                // horizontal viewing direction, and turns toward the other eye to converge.
+                let side = phi.cos() * u + phi.sin() * w;
+                let origin = frame.center + eye * side;
+                if let (Some(rig), true) = (self.stereo, eye != 0.) {
+                    phi -= f64::atan2(eye, rig.convergence);
+                }
+                let dir = theta.sin() * (phi.sin() * u - phi.cos() * w) + theta.cos() * v;
+
+                Some(Ray::new(origin, dir))
+            }
+        }
     }
 
-    fn sample_square(&self)->Vec3 {
+    fn sample_square(&self, sampler:&mut dyn Sampler)->Vec3 {
         // Returns the vector to a random point in the [-.5,-.5]-[+.5,+.5] unit square.
-        Vec3::new(rand::random::<f64>() % 1. - 0.5, rand::random::<f64>() % 1. - 0.5, 0.)
+        let (x, y) = sampler.get_2d();
+        Vec3::new(x - 0.5, y - 0.5, 0.)
 
     }
 
-    fn defocus_disk_sample(&self)->Vec3{
-        // Returns a random point in the camera defocus disk.
-        let p = random_in_unit_disk();
-        self.center + (p[0] * self.defocus_disk_u) + (p[1] * self.defocus_disk_v)
+    fn defocus_disk_sample(&self, frame:&RayFrame, sampler:&mut dyn Sampler)->Vec3{
+        // Returns a random point in the camera defocus disk, shaped by the aperture.
+        let p = self.aperture.sample(sampler);
+        frame.center + (p[0] * frame.defocus_disk_u) + (p[1] * frame.defocus_disk_v)
     }
 
-    fn ray_color(&self,ray:&mut Ray,depth:usize ,world:&dyn Hittable)->Color{
+    fn count_ray(&self, counter:Counter){
+        // Progress always gets the rays, the statistics only with the `stats` feature.
+        self.ray_count.set(self.ray_count.get() + 1);
+        stats::count(counter);
+    }
+
+    fn ray_color(&self,ray:&mut Ray,depth:usize ,scene:&Scene, bsdf_sample:Option<(Point3,f64)>, sampler:&mut dyn Sampler, mut aov:Option<AovVertex>)->Color{
+        // `bsdf_sample` holds the origin and the solid angle pdf of `ray` when it comes from a
+        // non specular bounce, in which case the lights were also sampled explicitly from there
+        // and what the ray finds is weighted by multiple importance sampling.
+        // `aov` is given for camera rays, to be filled from the first hit, and for the ray
+        // scattered from there, as the lights it finds are part of the direct lighting.
         // If we've exceeded the ray bounce limit, no more light is gathered.
-        if depth <= 0 {
+        if depth == 0 {
             return BLACK;
         }
         let mut rec = HitRecord::dummy();
-        
-        if world.hit(ray,Intervall::new(0.001,f64::INFINITY),&mut rec) {
-            let mut scattered=Ray { origine: Vec3::ZERO, direction: Vec3::ZERO };
-            let mut attenuation = Color::ZERO;
-            if rec.clone().mat.scatter(ray,&mut rec,&mut attenuation,&mut scattered) {
-                return attenuation * self.ray_color(&mut scattered, depth-1, world)
+        self.count_ray(if depth == self.max_depth { Counter::PrimaryRays } else { Counter::SecondaryRays });
+
+        if scene.world.hit(ray,Intervall::new(0.001,f64::INFINITY),&mut rec) {
+            let mut emitted = rec.mat.emitted(ray, &rec);
+            if let (Some(light), Some((origin, pdf))) = (rec.light_id, bsdf_sample) {
+                emitted = power_heuristic(pdf, scene.light_pdf(origin, light, ray.direction)) * emitted;
             }
-            return BLACK;
+
+            let mut srec = ScatterRecord::new();
+            let scattered = rec.clone().mat.scatter(ray,&mut rec,&mut srec, sampler);
+            // Recorded after scattering, which may perturb the shading normal.
+            if let Some(aov) = aov.as_mut() {
+                if let AovVertex::Camera(sample) = aov {
+                    sample.record_hit(ray, &rec);
+                }
+                if let Some(light) = rec.light_id {
+                    aov.add_light(light, emitted);
+                }
+            }
+            if scattered {
+                let mut per_light = match aov {
+                    Some(AovVertex::Camera(sample)) => Some(sample.lights.as_mut_slice()),
+                    _ => None,
+                };
+                let direct = self.direct_light(ray, &rec, scene, sampler, per_light.as_deref_mut());
+                let next_sample = if srec.skip_pdf {
+                    None
+                } else {
+                    Some((rec.p, rec.mat.scattering_pdf(ray, &rec, srec.scattered.direction)))
+                };
+                let next_aov = per_light.map(|lights| AovVertex::Bounce { lights, weight: srec.attenuation });
+                return emitted + direct + srec.attenuation * self.ray_color(&mut srec.scattered, depth-1, scene, next_sample, sampler, next_aov)
+            }
+            return emitted;
             
         }
-        let direction = normalize(ray.direction);
-        let a = 0.5*(direction.y() + 1.);
-        (1.0-a)*Color::new(1.0, 1.0, 1.0) + a*Color::new(0.5, 0.7, 1.0)
+
+        let mut color = match bsdf_sample {
+            Some((_, pdf)) => power_heuristic(pdf, scene.sky.pdf(ray.direction)) * scene.sky.radiance(ray.direction),
+            None => scene.sky.radiance(ray.direction),
+        };
+        for (i, light) in scene.infinite_lights(){
+            let mut le = light.le(ray.direction);
+            if let Some((origin, pdf)) = bsdf_sample {
+                le = power_heuristic(pdf, scene.light_pdf(origin, i, ray.direction)) * le;
+            }
+            if let Some(aov) = aov.as_mut() {
+                aov.add_light(i, le);
+            }
+            color += le;
+        }
+        color
     }
-}
\ No newline at end of file
+
+    fn direct_light(&self,ray:&Ray,rec:&HitRecord,scene:&Scene, sampler:&mut dyn Sampler, mut per_light:Option<&mut [Color]>)->Color{
+        // Sample the lights, and the environment when it supports it, with a shadow ray toward them.
+        // The contribution of each scene light is also added to `per_light` when given.
+        let mut color = BLACK;
+        let mut add_sample = |light: Option<usize>, sample: LightSample| {
+            let f = rec.mat.eval(ray, rec, sample.wi);
+            if f.near_zero(){
+                return;
+            }
+
+            let shadow_ray = Ray::new(rec.p, sample.wi);
+            self.count_ray(Counter::ShadowRays);
+            let mut shadow_rec = HitRecord::dummy();
+            if !scene.world.hit(&shadow_ray, Intervall::new(0.001, sample.distance - 0.001), &mut shadow_rec){
+                // Delta lights can't be reached by scattering, their samples take the full weight.
+                let weight = if sample.pdf > 0. {
+                    power_heuristic(sample.pdf, rec.mat.scattering_pdf(ray, rec, sample.wi))
+                } else {
+                    1.
+                };
+                let contribution = weight * f * sample.radiance;
+                if let (Some(per_light), Some(light)) = (per_light.as_deref_mut(), light)
+                    && let Some(light_color) = per_light.get_mut(light) {
+                    *light_color += contribution;
+                }
+                color += contribution;
+            }
+        };
+        for (light, sample) in scene.sample_lights(rec.p, sampler){
+            add_sample(Some(light), sample);
+        }
+        if let Some(sample) = scene.sky.sample(sampler){
+            add_sample(None, sample);
+        }
+        color
+    }
+}
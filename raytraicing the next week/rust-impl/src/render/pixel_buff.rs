@@ -1,4 +1,4 @@
-use std::{fs::File, io::Write};
+use std::{fs::File, io::{self, Write}};
 
 #[derive(Debug)]
 pub struct PixelBuff{
@@ -37,6 +37,70 @@ impl PixelBuff {
             s_file.push_str(&s_pixel);
         }
 
-        file.write(s_file.as_bytes()).unwrap();
+        file.write_all(s_file.as_bytes()).unwrap();
     }
-}
\ No newline at end of file
+}
+impl PixelBuff {
+    pub fn write_into_png(&self,file:&mut File)->io::Result<()>{
+        // Each scanline starts with its filter type, none here.
+        let mut raw = Vec::with_capacity(self.heigth * (3 * self.width + 1));
+        for row in self.pixels.chunks(self.width.max(1)).take(self.heigth){
+            raw.push(0);
+            raw.extend(row.iter().flatten());
+        }
+
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend((self.width as u32).to_be_bytes());
+        ihdr.extend((self.heigth as u32).to_be_bytes());
+        ihdr.extend([8, 2, 0, 0, 0]); // 8 bits RGB, deflate, no filter, no interlace
+
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        write_png_chunk(&mut png, b"IHDR", &ihdr);
+        write_png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        write_png_chunk(&mut png, b"IEND", &[]);
+        file.write_all(&png)
+    }
+}
+
+fn write_png_chunk(png:&mut Vec<u8>, kind:&[u8;4], data:&[u8]){
+    png.extend((data.len() as u32).to_be_bytes());
+    png.extend(kind);
+    png.extend(data);
+    png.extend(crc32(kind.iter().chain(data)).to_be_bytes());
+}
+
+fn crc32<'a>(bytes:impl Iterator<Item = &'a u8>)->u32{
+    let mut crc = 0xffff_ffff_u32;
+    for &byte in bytes{
+        crc ^= byte as u32;
+        for _ in 0..8{
+            crc = if crc & 1 == 1 { 0xedb8_8320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Zlib stream made of uncompressed deflate blocks, which every decoder reads.
+fn zlib_stored(data:&[u8])->Vec<u8>{
+    const MAX_BLOCK:usize = 0xffff;
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_BLOCK).peekable();
+    if blocks.peek().is_none(){
+        out.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next(){
+        let len = block.len() as u16;
+        out.push(blocks.peek().is_none() as u8); // Final block flag
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend(block);
+    }
+
+    let (mut a, mut b) = (1_u32, 0_u32);
+    for &byte in data{
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    out.extend((b << 16 | a).to_be_bytes());
+    out
+}
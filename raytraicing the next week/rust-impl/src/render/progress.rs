@@ -0,0 +1,124 @@
+use std::{
+    cell::Cell,
+    io::{stdout, Write},
+    time::{Duration, Instant},
+};
+
+use crate::render::limits::RenderOutcome;
+
+/// Snapshot of a render in progress.
+#[derive(Debug, Clone, Copy)]
+pub struct Progress {
+    pub completed_samples: u64,
+    pub total_samples: u64, // Upper bound, adaptive sampling may stop before
+    pub rays: u64,          // Camera, scattered and shadow rays traced so far
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    pub fn fraction(&self) -> f64 {
+        if self.total_samples == 0 {
+            1.
+        } else {
+            (self.completed_samples as f64 / self.total_samples as f64).min(1.)
+        }
+    }
+
+    /// Remaining time, assuming the samples left go as fast as the ones done.
+    pub fn eta(&self) -> Option<Duration> {
+        let fraction = self.fraction();
+        (fraction > 0.).then(|| self.elapsed.mul_f64((1. - fraction) / fraction))
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0. { self.rays as f64 / seconds } else { 0. }
+    }
+}
+
+/// Receives the progress of renders. Called often, implementations should throttle
+/// anything expensive.
+pub trait ProgressObserver {
+    #[allow(unused)]
+    fn on_start(&self, total_samples: u64) {}
+
+    fn on_progress(&self, progress: &Progress);
+
+    #[allow(unused)]
+    fn on_finish(&self, progress: &Progress, outcome: RenderOutcome) {}
+}
+
+/// Progress bar redrawn in place on stdout, with the rays per second and the remaining time.
+pub struct TerminalProgress {
+    width: usize,
+    last_draw: Cell<Option<Instant>>,
+}
+
+impl TerminalProgress {
+    const REFRESH: Duration = Duration::from_millis(100);
+
+    pub fn new() -> Self {
+        Self { width: 50, last_draw: Cell::new(None) }
+    }
+
+    fn draw(&self, progress: &Progress) {
+        let filled = (progress.fraction() * self.width as f64) as usize;
+        let eta = progress.eta().map_or("--:--".to_string(), |eta| {
+            let seconds = eta.as_secs();
+            format!("{:02}:{:02}", seconds / 60, seconds % 60)
+        });
+        print!(
+            "\r[{}{}] {:3.0}% {:7.2} Mrays/s ETA {eta}",
+            "#".repeat(filled),
+            " ".repeat(self.width - filled),
+            100. * progress.fraction(),
+            progress.rays_per_second() / 1e6,
+        );
+        let _ = stdout().flush();
+        self.last_draw.set(Some(Instant::now()));
+    }
+}
+
+impl Default for TerminalProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProgressObserver for TerminalProgress {
+    fn on_start(&self, _total_samples: u64) {
+        self.last_draw.set(None);
+    }
+
+    fn on_progress(&self, progress: &Progress) {
+        if self.last_draw.get().is_none_or(|last| last.elapsed() >= Self::REFRESH) {
+            self.draw(progress);
+        }
+    }
+
+    fn on_finish(&self, progress: &Progress, outcome: RenderOutcome) {
+        let message = match outcome {
+            // Adaptive sampling may have skipped samples, the render is still done.
+            RenderOutcome::Completed => {
+                self.draw(&Progress { completed_samples: progress.total_samples, ..*progress });
+                "done"
+            }
+            RenderOutcome::DeadlineReached => {
+                self.draw(progress);
+                "stopped at the deadline"
+            }
+            RenderOutcome::Cancelled => {
+                self.draw(progress);
+                "cancelled"
+            }
+        };
+        println!(" {message}");
+    }
+}
+
+/// Reports nothing, for library use.
+pub struct SilentProgress;
+
+impl ProgressObserver for SilentProgress {
+    fn on_progress(&self, _progress: &Progress) {}
+}
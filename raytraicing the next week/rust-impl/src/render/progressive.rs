@@ -0,0 +1,23 @@
+use std::{path::PathBuf, time::Duration};
+
+/// Renders the whole image in passes of `samples_per_pass` samples per pixel, instead of
+/// finishing each pixel in turn, optionally saving the film to a checkpoint file every
+/// `checkpoint_interval` and after the last pass. `Camera::resume` continues from it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Progressive {
+    pub samples_per_pass: usize,
+    pub checkpoint: Option<PathBuf>,
+    pub checkpoint_interval: Duration,
+}
+
+impl Progressive {
+    pub fn new(samples_per_pass: usize) -> Self {
+        Self { samples_per_pass: samples_per_pass.max(1), checkpoint: None, checkpoint_interval: Duration::ZERO }
+    }
+
+    pub fn with_checkpoint(mut self, path: impl Into<PathBuf>, interval: Duration) -> Self {
+        self.checkpoint = Some(path.into());
+        self.checkpoint_interval = interval;
+        self
+    }
+}
@@ -0,0 +1,20 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FisheyeMapping {
+    Equidistant, // Distance to the image center proportional to the angle
+    Equisolid,   // Equal areas on the image for equal solid angles
+}
+
+/// How camera rays are laid out over the image. All projections look from `lookfrom`
+/// toward `lookat`, `vup` being up in the image.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Projection {
+    /// Pinhole or thin lens, from `vfov`, `defocus_angle` and `focus_dist`.
+    #[default]
+    Perspective,
+    /// Parallel rays, for technical drawings. `height` is the viewport height in world units.
+    Orthographic { height: f64 },
+    /// Circular fisheye covering `fov` degrees across the image height.
+    Fisheye { fov: f64, mapping: FisheyeMapping },
+    /// Full 360° x 180° panorama, the image center looking at `lookat`.
+    Equirectangular,
+}
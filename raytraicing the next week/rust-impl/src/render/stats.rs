@@ -0,0 +1,152 @@
+use std::{
+    cell::Cell,
+    fmt::{Display, Write},
+    fs, io,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// Events counted while statistics are collected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Counter {
+    PrimaryRays,     // Rays leaving the camera
+    SecondaryRays,   // Rays scattered by surfaces
+    ShadowRays,      // Visibility tests toward sampled lights
+    AggregateVisits, // Aggregates entered: the world list, nested lists and meshes
+    PrimitiveTests,  // Ray against sphere, quad or triangle intersections
+}
+
+const COUNTER_COUNT: usize = 5;
+
+// Hittables have no render context to count into, so counters live with the rendering thread.
+// They only ever grow, statistics being differences between two snapshots.
+thread_local! {
+    static COUNTERS: [Cell<u64>; COUNTER_COUNT] = const { [const { Cell::new(0) }; COUNTER_COUNT] };
+}
+
+/// Adds one to `counter`, on this thread. Compiled out unless the `stats` feature is on, as
+/// it runs for every ray and primitive test.
+#[inline]
+pub fn count(counter: Counter) {
+    #[cfg(feature = "stats")]
+    COUNTERS.with(|counters| {
+        let c = &counters[counter as usize];
+        c.set(c.get() + 1);
+    });
+    #[cfg(not(feature = "stats"))]
+    let _ = counter;
+}
+
+fn snapshot() -> [u64; COUNTER_COUNT] {
+    COUNTERS.with(|counters| counters.each_ref().map(Cell::get))
+}
+
+/// Counters and phase timings of a render, to find out why a scene is slow.
+///
+/// Counts what happens between `start` and `finish`, on the thread that called `start`.
+/// Counters stay at 0 unless built with the `stats` feature, phases are always timed.
+#[derive(Debug, Clone, Default)]
+pub struct RenderStats {
+    pub primary_rays: u64,
+    pub secondary_rays: u64,
+    pub shadow_rays: u64,
+    pub aggregate_visits: u64,
+    pub primitive_tests: u64,
+    pub phases: Vec<(String, Duration)>, // In the order they were timed
+    start_counts: [u64; COUNTER_COUNT],
+}
+
+impl RenderStats {
+    /// Starts counting from the current counters of this thread.
+    pub fn start() -> Self {
+        Self { start_counts: snapshot(), ..Self::default() }
+    }
+
+    /// Runs `f`, recording how long it took under `name`.
+    pub fn time_phase<T>(&mut self, name: &str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.phases.push((name.to_string(), start.elapsed()));
+        result
+    }
+
+    /// Copies what the counters got since `start`.
+    pub fn finish(&mut self) {
+        let end_counts = snapshot();
+        let [primary, secondary, shadow, visits, tests] = std::array::from_fn(|k| end_counts[k] - self.start_counts[k]);
+        self.primary_rays = primary;
+        self.secondary_rays = secondary;
+        self.shadow_rays = shadow;
+        self.aggregate_visits = visits;
+        self.primitive_tests = tests;
+    }
+
+    pub fn total_rays(&self) -> u64 {
+        self.primary_rays + self.secondary_rays + self.shadow_rays
+    }
+
+    /// Segments per camera path, shadow rays aside.
+    pub fn average_path_length(&self) -> f64 {
+        if self.primary_rays == 0 {
+            0.
+        } else {
+            (self.primary_rays + self.secondary_rays) as f64 / self.primary_rays as f64
+        }
+    }
+
+    pub fn total_time(&self) -> Duration {
+        self.phases.iter().map(|(_, duration)| *duration).sum()
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut phases = String::new();
+        for (i, (name, duration)) in self.phases.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            let name = name.replace('\\', "\\\\").replace('"', "\\\"");
+            let _ = write!(phases, "{separator}\n    \"{name}\": {}", duration.as_secs_f64());
+        }
+        let phases_end = if self.phases.is_empty() { "" } else { "\n  " };
+
+        format!(
+            "{{\n  \"primary_rays\": {},\n  \"secondary_rays\": {},\n  \"shadow_rays\": {},\n  \"total_rays\": {},\n  \
+             \"aggregate_visits\": {},\n  \"primitive_tests\": {},\n  \"average_path_length\": {},\n  \
+             \"phase_seconds\": {{{phases}{phases_end}}}\n}}\n",
+            self.primary_rays,
+            self.secondary_rays,
+            self.shadow_rays,
+            self.total_rays(),
+            self.aggregate_visits,
+            self.primitive_tests,
+            self.average_path_length(),
+        )
+    }
+
+    pub fn save_json<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+}
+
+impl Display for RenderStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let per_ray = |count: u64| {
+            let rays = self.total_rays();
+            if rays == 0 { 0. } else { count as f64 / rays as f64 }
+        };
+
+        writeln!(f, "Render statistics")?;
+        if cfg!(feature = "stats") {
+            writeln!(f, "  primary rays        {:>14}", self.primary_rays)?;
+            writeln!(f, "  secondary rays      {:>14}", self.secondary_rays)?;
+            writeln!(f, "  shadow rays         {:>14}", self.shadow_rays)?;
+            writeln!(f, "  aggregate visits    {:>14} ({:.1} per ray)", self.aggregate_visits, per_ray(self.aggregate_visits))?;
+            writeln!(f, "  primitive tests     {:>14} ({:.1} per ray)", self.primitive_tests, per_ray(self.primitive_tests))?;
+            writeln!(f, "  average path length {:>14.2}", self.average_path_length())?;
+        } else {
+            writeln!(f, "  (counters need the stats feature)")?;
+        }
+        for (name, duration) in self.phases.iter() {
+            writeln!(f, "  {name:<19} {:>13.3}s", duration.as_secs_f64())?;
+        }
+        write!(f, "  {:<19} {:>13.3}s", "total", self.total_time().as_secs_f64())
+    }
+}
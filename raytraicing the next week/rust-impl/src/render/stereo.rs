@@ -0,0 +1,38 @@
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StereoLayout {
+    SideBySide, // Left eye on the left half of the image
+    TopBottom,  // Left eye on the top half of the image
+}
+
+/// Two eyes rendered into a single image. With the equirectangular projection, the eyes
+/// follow the viewing direction around the center (omni-directional stereo).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StereoRig {
+    pub interpupillary_distance: f64, // Distance between the eyes, in world units
+    pub convergence: f64,             // Distance at which the eyes' rays converge (zero parallax)
+    pub layout: StereoLayout,
+}
+
+impl StereoRig {
+    pub fn new(interpupillary_distance: f64, convergence: f64, layout: StereoLayout) -> Self {
+        Self { interpupillary_distance, convergence, layout }
+    }
+
+    /// Signed eye offsets along the camera right vector, with the position of each eye's
+    /// view in the output image, for a view of `width` x `height` pixels.
+    pub(super) fn eyes(&self, width: usize, height: usize) -> [(f64, (usize, usize)); 2] {
+        let half = self.interpupillary_distance / 2.;
+        let right_view = match self.layout {
+            StereoLayout::SideBySide => (width, 0),
+            StereoLayout::TopBottom => (0, height),
+        };
+        [(-half, (0, 0)), (half, right_view)]
+    }
+
+    pub(super) fn output_size(&self, width: usize, height: usize) -> (usize, usize) {
+        match self.layout {
+            StereoLayout::SideBySide => (2 * width, height),
+            StereoLayout::TopBottom => (width, 2 * height),
+        }
+    }
+}
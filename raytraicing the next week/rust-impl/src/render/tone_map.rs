@@ -0,0 +1,132 @@
+use std::str::FromStr;
+
+use crate::color::Color;
+
+/// Curve bringing scene radiance into the displayable [0, 1] range.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ToneMapper {
+    /// Clips everything brighter than white.
+    #[default]
+    Clamp,
+    /// Extended Reinhard on the luminance, keeping hues. `white_point` is the luminance mapped
+    /// to white, brighter values being clipped.
+    Reinhard { white_point: f64 },
+    /// Stephen Hill's fit of the ACES reference rendering and sRGB output transforms.
+    Aces,
+    /// Troy Sobotka's AgX with its default look, desaturating highlights toward white.
+    AgX,
+    /// John Hable's filmic curve from Uncharted 2.
+    Hable,
+}
+
+impl FromStr for ToneMapper {
+    type Err = String;
+
+    /// Parses `clamp`, `reinhard` (with a white point of 4), `aces`, `agx` or `hable`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "clamp" => Ok(ToneMapper::Clamp),
+            "reinhard" => Ok(ToneMapper::Reinhard { white_point: 4. }),
+            "aces" => Ok(ToneMapper::Aces),
+            "agx" => Ok(ToneMapper::AgX),
+            "hable" => Ok(ToneMapper::Hable),
+            _ => Err(format!("unknown tone mapper '{s}'")),
+        }
+    }
+}
+
+/// Exposure and tone curve applied when converting linear images to 8 bit output.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ToneMapping {
+    pub exposure: f64, // In stops, added to the camera exposure
+    pub operator: ToneMapper,
+}
+
+impl ToneMapping {
+    pub fn new(operator: ToneMapper, exposure: f64) -> Self {
+        Self { exposure, operator }
+    }
+
+    /// Maps a linear scene color to a linear display color in [0, 1], to be encoded to sRGB.
+    pub fn apply(&self, color: Color) -> Color {
+        let color = color * f64::exp2(self.exposure);
+        let mapped = match self.operator {
+            ToneMapper::Clamp => color,
+            ToneMapper::Reinhard { white_point } => {
+                let l = luminance(color);
+                if l <= 0. {
+                    color
+                } else {
+                    let l_out = l * (1. + l / (white_point * white_point)) / (1. + l);
+                    color * (l_out / l)
+                }
+            }
+            ToneMapper::Aces => {
+                let v = mul(ACES_INPUT, color);
+                let fit = |v: f64| (v * (v + 0.0245786) - 0.000090537) / (v * (0.983729 * v + 0.432951) + 0.238081);
+                mul(ACES_OUTPUT, Color::new(fit(v.x()), fit(v.y()), fit(v.z())))
+            }
+            ToneMapper::AgX => {
+                const MIN_EV: f64 = -12.47393;
+                const MAX_EV: f64 = 4.026069;
+                let v = mul(AGX_INSET, color);
+                let curve = |x: f64| {
+                    let x = (x.max(1e-10).log2().clamp(MIN_EV, MAX_EV) - MIN_EV) / (MAX_EV - MIN_EV);
+                    let (x2, x4) = (x * x, x * x * x * x);
+                    15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x - 0.00232
+                };
+                // The curve gives display encoded values, brought back to linear.
+                let v = mul(AGX_OUTSET, Color::new(curve(v.x()), curve(v.y()), curve(v.z())));
+                Color::new(v.x().max(0.).powf(2.2), v.y().max(0.).powf(2.2), v.z().max(0.).powf(2.2))
+            }
+            ToneMapper::Hable => {
+                const WHITE: f64 = 11.2;
+                const EXPOSURE_BIAS: f64 = 2.;
+                let curve = |x: f64| {
+                    let (a, b, c, d, e, f) = (0.15, 0.5, 0.1, 0.2, 0.02, 0.3);
+                    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+                };
+                let scale = curve(WHITE).recip();
+                Color::new(
+                    curve(EXPOSURE_BIAS * color.x()) * scale,
+                    curve(EXPOSURE_BIAS * color.y()) * scale,
+                    curve(EXPOSURE_BIAS * color.z()) * scale,
+                )
+            }
+        };
+        Color::new(mapped.x().clamp(0., 1.), mapped.y().clamp(0., 1.), mapped.z().clamp(0., 1.))
+    }
+}
+
+// Linear sRGB to the ACES fit input space, and back with the output transform folded in.
+const ACES_INPUT: [[f64; 3]; 3] = [
+    [0.59719, 0.35458, 0.04823],
+    [0.07600, 0.90834, 0.01566],
+    [0.02840, 0.13383, 0.83777],
+];
+const ACES_OUTPUT: [[f64; 3]; 3] = [
+    [1.60475, -0.53108, -0.07367],
+    [-0.10208, 1.10813, -0.00605],
+    [-0.00327, -0.07276, 1.07602],
+];
+
+// Linear sRGB to the AgX working space (primaries moved inward), and back out.
+const AGX_INSET: [[f64; 3]; 3] = [
+    [0.842479062253094, 0.0784335999999992, 0.0792237451477643],
+    [0.0423282422610123, 0.878468636469772, 0.0791661274605434],
+    [0.0423756549057051, 0.0784336, 0.879142973793104],
+];
+const AGX_OUTSET: [[f64; 3]; 3] = [
+    [1.19687900512017, -0.0980208811401368, -0.0990297440797205],
+    [-0.0528968517574562, 1.15190312990417, -0.0989611768448433],
+    [-0.0529716355144438, -0.0980434501171241, 1.15107367264116],
+];
+
+fn mul(m: [[f64; 3]; 3], c: Color) -> Color {
+    let row = |r: [f64; 3]| r[0] * c.x() + r[1] * c.y() + r[2] * c.z();
+    Color::new(row(m[0]), row(m[1]), row(m[2]))
+}
+
+fn luminance(c: Color) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
@@ -0,0 +1,87 @@
+use std::rc::Rc;
+
+use crate::{
+    color::Color,
+    geometry::Sphere,
+    light::{
+        hittable_list::HittableList,
+        light_tree::LightTree,
+        lights::{Light, LightSample, SphereLight},
+        material::DiffuseLightMat,
+        sky::{Environment, GradientSky},
+    },
+    math::{Point3, Sampler, Vec3},
+};
+
+pub struct Scene {
+    pub world: HittableList,
+    pub lights: Vec<Rc<dyn Light>>, // Added with `push_light`
+    pub sky: Rc<dyn Environment>,
+    pub seed: u64, // Seed of every random stream of the render
+    light_tree: Option<LightTree>,
+    infinite_lights: Vec<usize>, // Lights without bounds, the only ones rays escaping the world can reach
+}
+
+impl Scene {
+    pub fn new(world: HittableList) -> Self {
+        Self { world, lights: vec![], sky: Rc::new(GradientSky::default()), seed: 0, light_tree: None, infinite_lights: vec![] }
+    }
+
+    pub fn push_light(&mut self, light: Rc<dyn Light>) {
+        if light.bounds().is_none() {
+            self.infinite_lights.push(self.lights.len());
+        }
+        self.lights.push(light);
+        self.light_tree = None;
+    }
+
+    /// Adds an emissive sphere, both as geometry and as a light sampled explicitly.
+    pub fn push_emissive_sphere(&mut self, center: Point3, radius: f64, radiance: Color) {
+        let light_id = self.lights.len();
+        self.push_light(Rc::new(SphereLight::new(center, radius, radiance)));
+
+        let mat = Rc::new(DiffuseLightMat::new(radiance));
+        self.world.push(Rc::new(Sphere::new(center, radius, mat).with_light_id(light_id)));
+    }
+
+    /// Builds the light hierarchy, so that a single light, picked according to its estimated
+    /// contribution, is sampled per shading point instead of all of them.
+    /// Must be called again when lights are pushed afterward.
+    pub fn build_light_tree(&mut self) {
+        self.light_tree = Some(LightTree::new(&self.lights));
+    }
+
+    /// Samples the lights from `p`, the radiance of each sample being divided by the
+    /// probability of picking its light, as is its pdf. Samples come with the index of their light.
+    pub fn sample_lights<'a>(&'a self, p: Point3, sampler: &'a mut dyn Sampler) -> impl Iterator<Item = (usize, LightSample)> + 'a {
+        // With the tree, the single sample is taken right away, otherwise lights are sampled
+        // one by one as the iterator advances.
+        let (picked, sampler) = match &self.light_tree {
+            None => (None, Some(sampler)),
+            Some(tree) => {
+                let picked = tree.sample(p, sampler.get_1d()).and_then(|(i, pmf)| {
+                    let mut sample = self.lights[i].sample_li(p, sampler)?;
+                    sample.radiance /= pmf;
+                    sample.pdf *= pmf;
+                    Some((i, sample))
+                });
+                (picked, None)
+            }
+        };
+        let all = sampler.map(|sampler| {
+            self.lights.iter().enumerate().filter_map(move |(i, light)| Some((i, light.sample_li(p, sampler)?)))
+        });
+        picked.into_iter().chain(all.into_iter().flatten())
+    }
+
+    /// Indices of the lights whose `le` may be non-zero, the ones reached by rays leaving the world.
+    pub fn infinite_lights(&self) -> impl Iterator<Item = (usize, &Rc<dyn Light>)> {
+        self.infinite_lights.iter().map(|&i| (i, &self.lights[i]))
+    }
+
+    /// Solid angle density with which `sample_lights` would produce `wi` from the light `light`.
+    pub fn light_pdf(&self, p: Point3, light: usize, wi: Vec3) -> f64 {
+        let pmf = self.light_tree.as_ref().map_or(1., |tree| tree.pmf(p, light));
+        pmf * self.lights[light].pdf_li(p, wi)
+    }
+}